@@ -0,0 +1,20 @@
+//! Arbitrary `ApptInfo` JSON, embedded in an otherwise well-formed calendar page
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uwtennis::parse::{facility_datetime, parse_initial, reconcile};
+
+fuzz_target!(|data: &[u8]| {
+    // Escaped the way the site escapes attribute values
+    let json = String::from_utf8_lossy(data).replace('&', "&amp;").replace('"', "&quot;");
+    let html = format!(
+        r#"<input id="ApptInfo" value="{json}"><input id="hdnDates" value="[&quot;2025-03-14T00:00:00&quot;]">"#
+    );
+    if let Ok((appts, dates)) = parse_initial(&html) {
+        for appt in &appts {
+            facility_datetime(&appt.end_date);
+        }
+        reconcile(&appts, &dates).describe();
+    }
+});
@@ -0,0 +1,13 @@
+//! Arbitrary calendar pages through the same parsing `fetch_initial` and `check_program` do
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uwtennis::parse::{parse_initial, reconcile};
+
+fuzz_target!(|data: &[u8]| {
+    let html = String::from_utf8_lossy(data);
+    if let Ok((appts, dates)) = parse_initial(&html) {
+        reconcile(&appts, &dates).describe();
+    }
+});
@@ -0,0 +1,17 @@
+//! Arbitrary instance cards: the first line is the appointment ID, the rest the HTML
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uwtennis::parse::{Availability, classify_spots, parse_instance, price_amount};
+
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    let (appointment_id, html) = input.split_once('\n').unwrap_or((&input, ""));
+    let details = parse_instance(html, appointment_id);
+    classify_spots(&details.spots);
+    Availability::parse(&details.spots);
+    if let Some(price) = &details.price {
+        price_amount(price);
+    }
+});
@@ -0,0 +1,66 @@
+//! Headless-browser helpers, only compiled with the `browser` feature
+
+use chromiumoxide::Page;
+use chromiumoxide::browser::{Browser, BrowserConfig};
+use chromiumoxide::page::ScreenshotParams;
+use futures::StreamExt;
+use tokio::task::JoinHandle;
+
+use crate::error::{Error, Result};
+
+/// Launch a headless Chromium, open `url` and return a full-page PNG screenshot
+pub async fn screenshot(url: &str) -> Result<Vec<u8>> {
+    let (mut browser, handle) = launch().await?;
+    let result = async {
+        let page = open(&browser, url).await?;
+        let png = page
+            .screenshot(ScreenshotParams::builder().full_page(true).build())
+            .await?;
+        Ok::<_, Error>(png)
+    }
+    .await;
+
+    close(&mut browser, handle).await?;
+    result
+}
+
+/// Launch a headless Chromium, open `url` and return the rendered HTML
+pub async fn render(url: &str) -> Result<String> {
+    let (mut browser, handle) = launch().await?;
+    let result = async {
+        let page = open(&browser, url).await?;
+        Ok::<_, Error>(page.content().await?)
+    }
+    .await;
+
+    close(&mut browser, handle).await?;
+    result
+}
+
+async fn launch() -> Result<(Browser, JoinHandle<()>)> {
+    let config = BrowserConfig::builder().build().map_err(Error::Config)?;
+    let (browser, mut handler) = Browser::launch(config).await?;
+
+    // The handler drives the CDP connection and must be polled for the browser to work
+    let handle = tokio::spawn(async move {
+        while let Some(event) = handler.next().await {
+            if event.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((browser, handle))
+}
+
+async fn open(browser: &Browser, url: &str) -> Result<Page> {
+    let page = browser.new_page(url).await?;
+    page.wait_for_navigation().await?;
+    Ok(page)
+}
+
+async fn close(browser: &mut Browser, handle: JoinHandle<()>) -> Result<()> {
+    browser.close().await?;
+    let _ = handle.await;
+    Ok(())
+}
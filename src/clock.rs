@@ -0,0 +1,219 @@
+//! Time at the facility and the check schedule
+
+use chrono::{DateTime, TimeZone};
+use chrono_tz::Tz;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::time;
+
+use crate::config::BurstWindow;
+
+// All keys, dates and times-of-day are expressed in the facility's timezone,
+// regardless of the host's
+pub const FACILITY_TZ: Tz = chrono_tz::America::Toronto;
+
+// Source of time for scheduling and time-of-day checks, swappable in tests
+pub trait Clock {
+    fn now(&self) -> DateTime<Tz>;
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+// The real wall clock backed by tokio timers
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Tz> {
+        // Follow the server's clock when ours is known to be badly skewed
+        let skew = chrono::Duration::milliseconds(CLOCK_SKEW_MS.load(Ordering::Relaxed));
+        (chrono::Utc::now() + skew).with_timezone(&FACILITY_TZ)
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+        time::sleep(duration)
+    }
+}
+
+// Server time minus local time, only set while the skew exceeds the configured limit
+static CLOCK_SKEW_MS: AtomicI64 = AtomicI64::new(0);
+static CLOCK_SKEWED: AtomicBool = AtomicBool::new(false);
+
+/// Compare the server's Date header against the local clock and warn on large skew
+pub(crate) fn check_clock_skew(response: &reqwest::Response, max_skew: Duration) {
+    let Some(server_time) = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+    else {
+        return;
+    };
+
+    let skew = server_time.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    let skewed = skew.abs().to_std().is_ok_and(|skew| skew > max_skew);
+    let was_skewed = CLOCK_SKEWED.swap(skewed, Ordering::Relaxed);
+    CLOCK_SKEW_MS.store(if skewed { skew.num_milliseconds() } else { 0 }, Ordering::Relaxed);
+
+    if skewed && !was_skewed {
+        say!("⚠️ Host clock is off from the server by {}s, using server time", skew.num_seconds());
+    } else if !skewed && was_skewed {
+        say!("✅ Host clock is back in sync with the server");
+    }
+}
+
+// Fixed-rate check schedule that speeds up inside burst windows; the first check
+// runs immediately
+pub struct Schedule {
+    interval: Duration,
+    bursts: Vec<BurstWindow>,
+    next: Option<DateTime<Tz>>,
+}
+
+impl Schedule {
+    pub fn new(interval: Duration) -> Self {
+        Schedule { interval, bursts: Vec::new(), next: None }
+    }
+
+    pub fn with_bursts(mut self, bursts: Vec<BurstWindow>) -> Self {
+        self.bursts = bursts;
+        self
+    }
+
+    /// Check interval in effect at `at`: the fastest burst window covering it, if any
+    pub fn interval_at(&self, at: DateTime<Tz>) -> Duration {
+        self.bursts
+            .iter()
+            .filter(|burst| burst.covers(at.naive_local()))
+            .map(|burst| Duration::from_secs(burst.interval_seconds.max(1)))
+            .min()
+            .unwrap_or(self.interval)
+    }
+
+    /// When the check after one at `at` is due; a burst window opening sooner
+    /// pulls it forward to the window's start
+    fn after(&self, at: DateTime<Tz>) -> DateTime<Tz> {
+        let interval = chrono::Duration::from_std(self.interval_at(at)).unwrap_or(chrono::Duration::MAX);
+        let due = at.checked_add_signed(interval).unwrap_or(at);
+        [at.date_naive(), at.date_naive() + chrono::Days::new(1)]
+            .into_iter()
+            .flat_map(|day| {
+                self.bursts
+                    .iter()
+                    .filter(move |burst| burst.runs_on(day))
+                    .map(move |burst| day.and_time(burst.hours.start))
+            })
+            .filter_map(|start| FACILITY_TZ.from_local_datetime(&start).earliest())
+            .filter(|start| *start > at)
+            .fold(due, DateTime::min)
+    }
+
+    /// How long to wait from `now` until the next check, advancing the schedule
+    pub fn next_delay(&mut self, now: DateTime<Tz>) -> Duration {
+        match self.next {
+            Some(next) if next > now => {
+                self.next = Some(self.after(next));
+                (next - now).to_std().unwrap_or_default()
+            }
+            // First check, or we fell behind: run now and restart the cadence from here
+            _ => {
+                self.next = Some(self.after(now));
+                Duration::ZERO
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // Clock whose time only moves when something sleeps on it
+    pub(crate) struct ManualClock {
+        now: Cell<DateTime<Tz>>,
+    }
+
+    impl ManualClock {
+        pub(crate) fn at(time: &str) -> Self {
+            let naive = chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+            ManualClock { now: Cell::new(naive.and_local_timezone(FACILITY_TZ).unwrap()) }
+        }
+
+        pub(crate) fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + chrono::Duration::from_std(duration).unwrap());
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> DateTime<Tz> {
+            self.now.get()
+        }
+
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+            self.advance(duration);
+            std::future::ready(())
+        }
+    }
+
+    #[tokio::test]
+    async fn schedule_runs_at_fixed_rate() {
+        let clock = ManualClock::at("2025-03-14 12:00");
+        let start = clock.now();
+        let mut schedule = Schedule::new(Duration::from_secs(60));
+
+        // First check is immediate
+        clock.sleep(schedule.next_delay(clock.now())).await;
+        assert_eq!(clock.now(), start);
+
+        // A check that takes 10s doesn't push the next one back
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(schedule.next_delay(clock.now()), Duration::from_secs(50));
+        clock.sleep(Duration::from_secs(50)).await;
+        assert_eq!(clock.now() - start, chrono::Duration::seconds(60));
+    }
+
+    #[tokio::test]
+    async fn schedule_restarts_after_falling_behind() {
+        let clock = ManualClock::at("2025-03-14 12:00");
+        let mut schedule = Schedule::new(Duration::from_secs(60));
+        clock.sleep(schedule.next_delay(clock.now())).await;
+
+        // A slow cycle overruns the interval, so the next check runs right away
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(schedule.next_delay(clock.now()), Duration::ZERO);
+        assert_eq!(schedule.next_delay(clock.now()), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn schedule_speeds_up_in_burst_windows() {
+        let burst: crate::config::BurstWindow = toml::from_str(r#"
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            hours = "06:55-07:15"
+            interval_seconds = 10
+        "#).unwrap();
+        // A Friday, 30 seconds before registration opens
+        let clock = ManualClock::at("2025-03-14 06:54");
+        clock.advance(Duration::from_secs(30));
+        let mut schedule = Schedule::new(Duration::from_secs(60)).with_bursts(vec![burst.clone()]);
+        clock.sleep(schedule.next_delay(clock.now())).await;
+
+        // The next check is pulled forward to the window's start, then runs every 10s
+        assert_eq!(schedule.next_delay(clock.now()), Duration::from_secs(30));
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(schedule.interval_at(clock.now()), Duration::from_secs(10));
+        assert_eq!(schedule.next_delay(clock.now()), Duration::from_secs(10));
+
+        // Back to the normal interval once the window closes
+        let closing = ManualClock::at("2025-03-14 07:14");
+        closing.advance(Duration::from_secs(55));
+        let mut schedule = Schedule::new(Duration::from_secs(60)).with_bursts(vec![burst]);
+        closing.sleep(schedule.next_delay(closing.now())).await;
+        assert_eq!(schedule.next_delay(closing.now()), Duration::from_secs(10));
+        closing.advance(Duration::from_secs(10));
+        assert_eq!(schedule.next_delay(closing.now()), Duration::from_secs(60));
+
+        // Weekends keep the normal pace
+        let saturday = ManualClock::at("2025-03-15 07:00");
+        assert_eq!(schedule.interval_at(saturday.now()), Duration::from_secs(60));
+    }
+}
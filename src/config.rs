@@ -0,0 +1,1196 @@
+//! Settings loaded from `config.toml`
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::Arc;
+
+use crate::cron::CronSchedule;
+use crate::error::{Error, Result};
+use crate::parse::{Availability, SpotInfo, classify_spots, compare_availability};
+
+// Config struct to parse config.toml
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    // Check interval for programs without their own `cron` schedule
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    // Windows checked more often, e.g. around when registration opens
+    #[serde(default)]
+    pub burst_windows: Vec<BurstWindow>,
+    // Primary ntfy topic; shorthand for an ntfy entry in `notifiers`
+    #[serde(default)]
+    pub ntfy_endpoint: Option<String>,
+    // Access token for an ntfy server that requires login
+    #[serde(default)]
+    pub ntfy_token: Option<Secret>,
+    // Or a username and password
+    #[serde(default)]
+    pub ntfy_username: Option<String>,
+    #[serde(default)]
+    pub ntfy_password: Option<Secret>,
+    // Every destination a notification fans out to
+    #[serde(default)]
+    pub notifiers: Vec<NotifierEntry>,
+    pub program_ids: Vec<ProgramConfig>,
+    // Only check sessions within this many days from today
+    #[serde(default)]
+    pub lookahead_days: Option<u64>,
+    // Only check sessions between these dates
+    #[serde(default)]
+    pub date_range: Option<DateRange>,
+    // Tracked sessions are saved here after every cycle and reloaded on startup
+    #[serde(default)]
+    pub state_path: Option<String>,
+    // Sessions that have started are appended here as JSON Lines, with their final fill state
+    #[serde(default)]
+    pub history_path: Option<String>,
+    // Sessions marked as attended with `uwtennis attend`
+    #[serde(default)]
+    pub journal_path: Option<String>,
+    // Save a calendar screenshot here when a program page fails to parse
+    #[serde(default)]
+    pub screenshot_dir: Option<String>,
+    // Attach a calendar screenshot to change notifications
+    #[serde(default)]
+    pub attach_screenshots: bool,
+    // Render the calendar in a headless browser when the plain fetch returns an unreadable page
+    #[serde(default)]
+    pub browser_fallback: bool,
+    // Notify when a session's price changes
+    #[serde(default)]
+    pub notify_price_changes: bool,
+    // Notify when a free session shows up for the first time
+    #[serde(default)]
+    pub notify_free_drop_ins: bool,
+    // Memberships/passes I hold; sessions requiring anything else are skipped
+    #[serde(default)]
+    pub memberships: Vec<String>,
+    // Public channel that gets open-spot announcements for the community
+    #[serde(default)]
+    pub broadcast: Option<BroadcastConfig>,
+    // Additional people sharing this instance, each with their own preferences
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+    // Friends' usual weekly availability, noted on open-spot alerts
+    #[serde(default)]
+    pub friends: Vec<FriendConfig>,
+    // TOML file with more `[[friends]]` entries, e.g. one shared by the group
+    #[serde(default)]
+    pub friends_path: Option<String>,
+    // Where I'd be coming from, for travel times and skipping sessions I can't reach
+    #[serde(default)]
+    pub travel: Option<TravelConfig>,
+    // Add a map link to the session's facility to alerts
+    #[serde(default)]
+    pub map_links: Option<MapProvider>,
+    // Facility details by session location, added to or overriding the built-in ones
+    #[serde(default)]
+    pub facilities: HashMap<String, FacilityConfig>,
+    // Skip outdoor sessions that end after dark
+    #[serde(default)]
+    pub sunset_guard: Option<SunsetGuardConfig>,
+    // Weekly Telegram/Discord poll for picking which open session to play
+    #[serde(default)]
+    pub voting: Option<VotingConfig>,
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+    // Retries with exponential backoff for site requests and notification sends
+    #[serde(default)]
+    pub retry: RetryConfig,
+    // Window in facility time during which changes are tracked but nothing is sent
+    #[serde(default)]
+    pub quiet_hours: Option<TimeWindow>,
+    // Send what was held through quiet hours as one digest when they end, rather than drop it
+    #[serde(default = "default_true")]
+    pub quiet_hours_digest: bool,
+    // Programs checked at the same time; unlimited when unset
+    #[serde(default)]
+    pub max_concurrent_checks: Option<usize>,
+    // Requests per second to the site, shared by every program check
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    // Shorthands for one notifier each of these types, used alongside `[[notifiers]]`:
+    // repository issue used as a shared log of availability changes
+    #[serde(default)]
+    pub issue_tracker: Option<IssueTrackerConfig>,
+    // signal-cli REST API used to message Signal contacts or groups
+    #[serde(default)]
+    pub signal: Option<SignalConfig>,
+    // WhatsApp Business Cloud API template messages
+    #[serde(default)]
+    pub whatsapp: Option<WhatsAppConfig>,
+    // Microsoft Teams incoming webhook
+    #[serde(default)]
+    pub teams: Option<TeamsConfig>,
+    // Groups of related programs shown side by side per timeslot
+    #[serde(default)]
+    pub comparisons: Vec<ComparisonConfig>,
+    // Calendar that open sessions are published to
+    #[serde(default)]
+    pub caldav: Option<CalDavConfig>,
+    // Static availability files for a public page, written every cycle
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    // Local git repository with a commit for every change in availability
+    #[serde(default)]
+    pub git_history: Option<GitHistoryConfig>,
+    // Other instances whose public mirror to follow, their changes sent through our notifiers
+    #[serde(default)]
+    pub peers: Vec<PeerConfig>,
+    // Notify when a program's check health changes (ok / degraded / failing)
+    #[serde(default)]
+    pub notify_health: bool,
+    // Send a notification when the monitor is stopped
+    #[serde(default)]
+    pub notify_on_shutdown: bool,
+    // Kill switch for every outgoing notification, whatever else is configured
+    #[serde(default = "default_true")]
+    pub enable_notifications: bool,
+    // Kill switch for inbound command handling (the Telegram bot commands)
+    #[serde(default = "default_true")]
+    pub enable_server: bool,
+    // Sessions not refreshed for this long are flagged stale; defaults to three check intervals
+    #[serde(default)]
+    pub stale_after_seconds: Option<u64>,
+    // Reuse each program's calendar (which sessions exist, their names and times) for this
+    // long, only asking for spot counts every cycle; 0 fetches it every cycle
+    #[serde(default)]
+    pub catalog_ttl_seconds: u64,
+    // Number of initial cycles during which no notifications are sent
+    #[serde(default)]
+    pub warmup_cycles: u64,
+    // What to do with sessions seen for the first time
+    #[serde(default)]
+    pub on_first_seen: FirstSeen,
+    // Console output and notifications without emoji or symbols, with fields spelled out
+    #[serde(default)]
+    pub plain_text: bool,
+    // Render spot changes as Markdown (strikethrough old, bold new, direction arrow)
+    #[serde(default)]
+    pub ntfy_markdown: bool,
+    // Warn and correct local time when the host clock is off from the server's by more than this
+    #[serde(default = "default_max_clock_skew_seconds")]
+    pub max_clock_skew_seconds: u64,
+    // Where the calendar is fetched from; registration links always point at the real site
+    #[serde(default = "default_site_url")]
+    pub site_url: String,
+    // Extra spots badge formats as regexes whose `open` group is the number of open
+    // spots, tried before the built-in ones
+    #[serde(default)]
+    pub spot_patterns: Vec<SpotPattern>,
+    // Abort reading any site response larger than this many bytes
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_site_url() -> String {
+    crate::fetch::SITE_URL.into()
+}
+
+fn default_interval_seconds() -> u64 {
+    60
+}
+
+fn default_max_clock_skew_seconds() -> u64 {
+    30
+}
+
+fn default_max_response_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+impl Config {
+    /// How old a session's spots can get before it's flagged stale
+    pub fn stale_after(&self) -> chrono::Duration {
+        let seconds = self.stale_after_seconds.unwrap_or(self.interval_seconds.saturating_mul(3));
+        chrono::Duration::seconds(i64::try_from(seconds).unwrap_or(i64::MAX))
+    }
+
+    fn program(&self, program_id: &str) -> Option<&ProgramConfig> {
+        self.program_ids.iter().find(|program| &*program.id == program_id)
+    }
+
+    /// Console label for the programs listing a session
+    pub fn program_label(&self, spot: &SpotInfo) -> String {
+        match self.program(&spot.program_id) {
+            Some(program) => std::iter::once(program.label())
+                .chain(spot.also_listed_under.iter().map(|(id, name)| {
+                    self.program(id).map_or_else(|| name.to_string(), ProgramConfig::label)
+                }))
+                .collect::<Vec<_>>()
+                .join(" + "),
+            None => spot.programs_label(),
+        }
+    }
+
+    /// Notification tags for the programs listing a session: their emoji, if they have one
+    pub fn program_tags(&self, spot: &SpotInfo) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        for emoji in spot.program_ids().filter_map(|id| self.program(id)?.emoji.clone()) {
+            if !tags.contains(&emoji) {
+                tags.push(emoji);
+            }
+        }
+        tags
+    }
+
+    /// Which spot changes to a program's sessions are worth an alert
+    pub fn notify_on(&self, program_id: &str) -> NotifyOn {
+        self.program(program_id).map(|program| program.notify_on).unwrap_or_default()
+    }
+
+    /// Whether sessions on `date` are within `lookahead_days` of `today` and inside `date_range`
+    pub fn checks_date(&self, date: NaiveDate, today: NaiveDate) -> bool {
+        let within_lookahead = self
+            .lookahead_days
+            .is_none_or(|days| (date - today).num_days() <= i64::try_from(days).unwrap_or(i64::MAX));
+        let within_range = self
+            .date_range
+            .as_ref()
+            .is_none_or(|range| (range.start..=range.end).contains(&date));
+        within_lookahead && within_range
+    }
+
+    /// Whether the session has as many open spots as a program's `min_spots` asks for
+    pub fn meets_min_spots(&self, program_id: &str, spot: &SpotInfo) -> bool {
+        self.program(program_id).is_none_or(|program| program.has_min_spots(&spot.spots))
+    }
+
+    /// `ntfy_endpoint` and the `[signal]`, `[whatsapp]`, `[teams]` and `[issue_tracker]`
+    /// tables, followed by everything under `[[notifiers]]`
+    pub fn notifiers(&self) -> Vec<NotifierEntry> {
+        self.notifiers_with_ntfy(self.ntfy_endpoint.as_ref())
+    }
+
+    /// Same as `notifiers`, with the program's own `ntfy_endpoint` in place of the global one
+    pub fn program_notifiers(&self, program: &ProgramConfig) -> Vec<NotifierEntry> {
+        self.notifiers_with_ntfy(program.ntfy_endpoint.as_ref().or(self.ntfy_endpoint.as_ref()))
+    }
+
+    fn notifiers_with_ntfy(&self, ntfy_endpoint: Option<&String>) -> Vec<NotifierEntry> {
+        ntfy_endpoint
+            .into_iter()
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(|endpoint| {
+                NotifierConfig::Ntfy(NtfyConfig {
+                    endpoint: endpoint.clone(),
+                    token: self.ntfy_token.clone(),
+                    username: self.ntfy_username.clone(),
+                    password: self.ntfy_password.clone(),
+                })
+            })
+            .chain(self.signal.clone().map(NotifierConfig::Signal))
+            .chain(self.whatsapp.clone().map(NotifierConfig::WhatsApp))
+            .chain(self.teams.clone().map(NotifierConfig::Teams))
+            .chain(self.issue_tracker.clone().map(NotifierConfig::IssueTracker))
+            .map(|notifier| NotifierEntry { notifier, digest: false })
+            .chain(self.notifiers.iter().cloned())
+            .collect()
+    }
+}
+
+// One `[[notifiers]]` entry
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotifierEntry {
+    #[serde(flatten)]
+    pub notifier: NotifierConfig,
+    // Hold session notifications back and send one summary at the end of each cycle
+    #[serde(default)]
+    pub digest: bool,
+}
+
+impl std::fmt::Display for NotifierEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.notifier)?;
+        if self.digest {
+            f.write_str(" (digest)")?;
+        }
+        Ok(())
+    }
+}
+
+// A notification destination, selected by its `type`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    Ntfy(NtfyConfig),
+    Webhook(WebhookConfig),
+    Discord(DiscordConfig),
+    Telegram(TelegramConfig),
+    Email(EmailConfig),
+    // signal-cli REST API, messaging Signal contacts or groups
+    Signal(SignalConfig),
+    // WhatsApp Business Cloud API template messages
+    WhatsApp(WhatsAppConfig),
+    // Adaptive Cards posted to a Microsoft Teams incoming webhook
+    Teams(TeamsConfig),
+    // Comments on (or new issues in) a GitHub or Gitea repository
+    #[serde(rename = "issue_tracker")]
+    IssueTracker(IssueTrackerConfig),
+    // Print notifications to the console
+    Stdout,
+    // Record notifications for tests, with injectable latency and failures
+    Debug(DebugConfig),
+}
+
+impl std::fmt::Display for NotifierConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifierConfig::Ntfy(ntfy) => write!(f, "ntfy {}", ntfy.endpoint),
+            NotifierConfig::Webhook(webhook) => write!(f, "webhook {}", webhook.url),
+            NotifierConfig::Discord(_) => f.write_str("Discord webhook"),
+            NotifierConfig::Telegram(telegram) => write!(f, "Telegram chat {}", telegram.chat_id),
+            NotifierConfig::Email(email) => write!(f, "email {}", email.to.join(", ")),
+            NotifierConfig::Signal(signal) => write!(f, "Signal {}", signal.recipients.join(", ")),
+            NotifierConfig::WhatsApp(whatsapp) => write!(f, "WhatsApp {}", whatsapp.recipients.join(", ")),
+            NotifierConfig::Teams(_) => f.write_str("Teams webhook"),
+            NotifierConfig::IssueTracker(tracker) => write!(f, "issues of {}", tracker.repo),
+            NotifierConfig::Stdout => f.write_str("stdout"),
+            NotifierConfig::Debug(debug) => match &debug.path {
+                Some(path) => write!(f, "debug buffer \"{}\" and {}", debug.buffer, path),
+                None => write!(f, "debug buffer \"{}\"", debug.buffer),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NtfyConfig {
+    pub endpoint: String,
+    // Sent as a Bearer token; takes precedence over username/password
+    #[serde(default)]
+    pub token: Option<Secret>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret>,
+}
+
+// A secret written inline, or read from an environment variable with `{ env = "NAME" }`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Secret {
+    Value(String),
+    Env { env: String },
+}
+
+impl Secret {
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            Secret::Value(value) => Ok(value.clone()),
+            Secret::Env { env } => std::env::var(env)
+                .map_err(|e| Error::Config(format!("can't read secret from ${env}: {e}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EmailConfig {
+    // SMTP server host, e.g. smtp.gmail.com
+    pub server: String,
+    // Defaults to the standard port for the TLS mode
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    // e.g. "Tennis bot <bot@example.com>"
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub tls: EmailTls,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTls {
+    // Upgrade a plain connection (port 587)
+    #[default]
+    Starttls,
+    // TLS from the start (port 465)
+    Tls,
+    // Unencrypted, for a local relay
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TelegramConfig {
+    // From @BotFather
+    pub bot_token: String,
+    pub chat_id: ChatId,
+    // Answer /status, /pause and /resume sent from this chat
+    #[serde(default)]
+    pub commands: bool,
+}
+
+// Telegram chats are numeric IDs, or "@name" for public channels
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Name(String),
+}
+
+impl std::fmt::Display for ChatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatId::Id(id) => write!(f, "{id}"),
+            ChatId::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DiscordConfig {
+    // Channel webhook, https://discord.com/api/webhooks/...
+    pub webhook_url: String,
+    // Overrides the webhook's default name
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DebugConfig {
+    // In-memory buffer the notifications are kept in, read back with `debug::recorded`
+    #[serde(default)]
+    pub buffer: String,
+    // Also append each notification here as a JSON line
+    #[serde(default)]
+    pub path: Option<String>,
+    // Wait this long before every send
+    #[serde(default)]
+    pub latency_ms: u64,
+    // Fail every Nth send; 1 fails them all
+    #[serde(default)]
+    pub fail_every: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebhookConfig {
+    // Receives a JSON POST per notification
+    pub url: String,
+    // Extra request headers, e.g. an Authorization token
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FirstSeen {
+    // Start tracking without a word
+    Silent,
+    // Log the new session to the console
+    #[default]
+    Log,
+    // Log it, and notify right away if it already has an open spot
+    NotifyIfAvailable,
+}
+
+// Tokio runtime shape; a single thread is plenty for this I/O-bound workload
+#[derive(Debug, Default, Deserialize)]
+pub struct RuntimeConfig {
+    #[serde(default)]
+    pub flavor: RuntimeFlavor,
+    // Only used by the multi-thread runtime; defaults to one per core. Zero is
+    // rejected when loading, since tokio can't start a runtime without workers
+    #[serde(default)]
+    pub worker_threads: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    // Tries per request, including the first; 1 turns retrying off
+    pub max_attempts: u32,
+    // Wait before the first retry, doubling for each one after it
+    pub base_delay_ms: u64,
+    // Longest wait between two tries
+    pub max_delay_ms: u64,
+}
+
+impl RetryConfig {
+    pub const DEFAULT: RetryConfig = RetryConfig { max_attempts: 3, base_delay_ms: 500, max_delay_ms: 10_000 };
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    // Sustained rate; 0 turns limiting off
+    pub requests_per_second: f64,
+    // Requests that can go out back to back before the rate applies; at least 1,
+    // or no request could ever go out
+    pub burst: NonZeroU32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig { requests_per_second: 2.0, burst: NonZeroU32::new(5).unwrap() }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeFlavor {
+    #[default]
+    MultiThread,
+    CurrentThread,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IssueTrackerConfig {
+    // API base, e.g. https://api.github.com or https://gitea.example.com/api/v1
+    pub api_url: String,
+    // "owner/name"
+    pub repo: String,
+    pub token: String,
+    // Comment on this issue; when unset, every event opens a new issue
+    #[serde(default)]
+    pub issue: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SignalConfig {
+    // signal-cli REST API base, e.g. http://localhost:8080
+    pub api_url: String,
+    // Registered sender number
+    pub number: String,
+    // Phone numbers and/or group IDs ("group.…")
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WhatsAppConfig {
+    pub token: String,
+    pub phone_number_id: String,
+    // Approved message template with a single body parameter for the change text
+    pub template: String,
+    #[serde(default = "default_whatsapp_language")]
+    pub language: String,
+    pub recipients: Vec<String>,
+}
+
+fn default_whatsapp_language() -> String {
+    "en_US".into()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TeamsConfig {
+    pub webhook_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComparisonConfig {
+    pub name: String,
+    // Program names or IDs to compare
+    pub programs: Vec<String>,
+}
+
+impl ComparisonConfig {
+    pub fn includes(&self, spot: &SpotInfo) -> bool {
+        self.programs.iter().any(|program| spot.listed_under(program))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MirrorConfig {
+    // Directory the files are written to, e.g. a web root or a git checkout
+    pub dir: String,
+    // availability.json with every tracked session
+    #[serde(default = "default_true")]
+    pub json: bool,
+    // index.html rendering the same as a table
+    #[serde(default = "default_true")]
+    pub html: bool,
+    #[serde(default = "default_mirror_title")]
+    pub title: String,
+    // Commit and push `dir` whenever availability changes; it must be a clone with a remote
+    #[serde(default)]
+    pub git_push: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHistoryConfig {
+    // Repository directory; created and initialized if missing
+    pub dir: String,
+    // Also push each commit to the repository's upstream
+    #[serde(default)]
+    pub push: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PeerConfig {
+    // Shown in notifications, e.g. "Spot change: Drop-in (via Sam)"
+    pub name: String,
+    // The peer's mirrored availability.json
+    pub url: String,
+}
+
+fn default_mirror_title() -> String {
+    "Tennis drop-in availability".into()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalDavConfig {
+    // Calendar collection URL, e.g. https://cloud.example.com/remote.php/dav/calendars/me/tennis/
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserConfig {
+    pub name: String,
+    pub ntfy_endpoint: String,
+    // Program names or IDs this user cares about; empty means all of them
+    #[serde(default)]
+    pub programs: Vec<String>,
+    // Only notify when the session has an open spot
+    #[serde(default)]
+    pub only_open: bool,
+    // Window during which this user gets no notifications
+    #[serde(default)]
+    pub quiet_hours: Option<TimeWindow>,
+    // Map app this user's alerts link the facility in
+    #[serde(default)]
+    pub map_links: Option<MapProvider>,
+}
+
+impl UserConfig {
+    /// Whether this user wants to hear about a change to `spot` at time of day `now`
+    pub fn wants(&self, spot: &SpotInfo, now: NaiveTime) -> bool {
+        let program_match = self.programs.is_empty()
+            || self.programs.iter().any(|program| spot.listed_under(program));
+        let open_match = !self.only_open || classify_spots(&spot.spots).is_open();
+        let quiet = self.quiet_hours.is_some_and(|window| window.contains(now));
+
+        program_match && open_match && !quiet
+    }
+
+    /// The user's ntfy topic, to send what they want to hear about through
+    pub fn notifier(&self) -> NotifierEntry {
+        ntfy_topic(&self.ntfy_endpoint)
+    }
+}
+
+/// A plain ntfy topic without credentials, for users and the broadcast channel
+fn ntfy_topic(endpoint: &str) -> NotifierEntry {
+    NotifierEntry {
+        notifier: NotifierConfig::Ntfy(NtfyConfig { endpoint: endpoint.to_string(), token: None, username: None, password: None }),
+        digest: false,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FriendConfig {
+    pub name: String,
+    // Weekly slots this friend can usually play
+    pub free: Vec<FreeSlot>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FreeSlot {
+    // "mon", "tuesday", ...; empty means every day
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    // e.g. "18:00-22:00"
+    pub hours: TimeWindow,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TravelConfig {
+    // [latitude, longitude]
+    pub home: [f64; 2],
+    #[serde(default)]
+    pub mode: TravelMode,
+    // Facility coordinates by session location, e.g. PAC = [43.4722, -80.5460];
+    // otherwise taken from the facility registry
+    #[serde(default)]
+    pub facilities: HashMap<String, [f64; 2]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapProvider {
+    Google,
+    Apple,
+}
+
+// What's known about the place a session is held
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FacilityConfig {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    // [latitude, longitude]
+    #[serde(default)]
+    pub coordinates: Option<[f64; 2]>,
+    #[serde(default)]
+    pub outdoor: bool,
+    #[serde(default)]
+    pub map_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SunsetGuardConfig {
+    // Session locations that are outdoor courts without lights, besides
+    // facilities marked `outdoor`
+    #[serde(default)]
+    pub locations: Vec<String>,
+    // [latitude, longitude] used for sunset times when the facility's aren't known;
+    // defaults to UW's main campus
+    #[serde(default = "default_sunset_coordinates")]
+    pub coordinates: [f64; 2],
+}
+
+fn default_sunset_coordinates() -> [f64; 2] {
+    [43.4723, -80.5449]
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TravelMode {
+    Walk,
+    #[default]
+    Bike,
+    Transit,
+    Drive,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VotingConfig {
+    // When the poll is posted, e.g. day = "sun", time = "18:00"
+    pub day: Weekday,
+    pub time: NaiveTime,
+    // How long voting stays open before the winner is announced
+    #[serde(default = "default_voting_hours")]
+    pub hours: u64,
+    // Open sessions starting within this many days are put to the vote
+    #[serde(default = "default_voting_days")]
+    pub days: u64,
+}
+
+fn default_voting_hours() -> u64 {
+    24
+}
+
+fn default_voting_days() -> u64 {
+    7
+}
+
+// Inclusive span of dates, e.g. `{ start = "2025-09-02", end = "2025-12-05" }`
+#[derive(Debug, Clone, Deserialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BurstWindow {
+    // "mon", "tuesday", ...; empty means every day
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    // e.g. "06:55-07:15"
+    pub hours: TimeWindow,
+    // Check interval inside the window
+    pub interval_seconds: u64,
+}
+
+impl BurstWindow {
+    /// Whether `at` falls inside the window; a window wrapping past midnight
+    /// belongs to the day it starts on
+    pub fn covers(&self, at: NaiveDateTime) -> bool {
+        let day = if self.hours.start > self.hours.end && at.time() < self.hours.end {
+            at.date().pred_opt().unwrap_or(at.date())
+        } else {
+            at.date()
+        };
+        self.hours.contains(at.time()) && self.runs_on(day)
+    }
+
+    /// Whether the window opens on `day`
+    pub fn runs_on(&self, day: NaiveDate) -> bool {
+        self.days.is_empty() || self.days.contains(&day.weekday())
+    }
+}
+
+// A case-insensitive regex for a spots badge, with an `open` capture group
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SpotPattern(pub Regex);
+
+impl TryFrom<String> for SpotPattern {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let regex = RegexBuilder::new(&value)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("invalid spot pattern \"{value}\": {e}"))?;
+        if !regex.capture_names().any(|name| name == Some("open")) {
+            return Err(format!("spot pattern \"{value}\" needs an (?<open>...) group for the number of open spots"));
+        }
+        Ok(SpotPattern(regex))
+    }
+}
+
+// A daily time-of-day window like "23:00-07:00", which may wrap past midnight
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| format!("invalid time window \"{value}\", expected HH:MM-HH:MM"))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|e| format!("invalid time \"{}\" in window \"{value}\": {e}", time.trim()))
+        };
+        Ok(TimeWindow { start: parse(start)?, end: parse(end)? })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastConfig {
+    pub endpoint: String,
+    #[serde(default = "default_broadcast_title")]
+    pub title: String,
+    #[serde(default = "default_broadcast_template")]
+    pub template: String,
+}
+
+impl BroadcastConfig {
+    pub fn notifier(&self) -> NotifierEntry {
+        ntfy_topic(&self.endpoint)
+    }
+}
+
+fn default_broadcast_title() -> String {
+    "Open spot: {product}".into()
+}
+
+fn default_broadcast_template() -> String {
+    "{spots} for {program} ({product}) on {date} @ {time} - anyone want to play?".into()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProgramConfig {
+    pub id: Arc<str>,
+    pub name: Arc<str>,
+    // Shown next to the program in console output and sent as a notification tag
+    #[serde(default)]
+    pub emoji: Option<String>,
+    // Console color for the program name
+    #[serde(default)]
+    pub color: Option<Color>,
+    // Send this program's alerts to this ntfy topic instead of the global `ntfy_endpoint`
+    #[serde(default)]
+    pub ntfy_endpoint: Option<String>,
+    // Which spot changes are worth an alert
+    #[serde(default)]
+    pub notify_on: NotifyOn,
+    // Only alert when at least this many spots are open, e.g. 2 for doubles
+    #[serde(default)]
+    pub min_spots: Option<u32>,
+    // Only track sessions on these days ("tue", "thursday", ...); empty means every day
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    // Only track sessions starting within this window, e.g. "17:00-21:00"
+    #[serde(default)]
+    pub time_range: Option<TimeWindow>,
+    // Check on this cron schedule in facility time, e.g. "*/2 7-23 * * *", instead
+    // of every `interval_seconds`
+    #[serde(default)]
+    pub cron: Option<CronSchedule>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    // Every change
+    #[default]
+    Any,
+    // Only when spots open up or the count goes up
+    Increase,
+    // Only when spots fill up or the count goes down
+    Decrease,
+}
+
+impl NotifyOn {
+    pub fn allows(self, previous: &str, current: &str) -> bool {
+        match self {
+            NotifyOn::Any => true,
+            NotifyOn::Increase => compare_availability(previous, current) == Ordering::Greater,
+            NotifyOn::Decrease => compare_availability(previous, current) == Ordering::Less,
+        }
+    }
+}
+
+impl ProgramConfig {
+    /// Whether a session starting at `start` falls on one of the program's `days`
+    /// and inside its `time_range`
+    pub fn includes(&self, start: NaiveDateTime) -> bool {
+        (self.days.is_empty() || self.days.contains(&start.weekday()))
+            && self.time_range.is_none_or(|window| window.contains(start.time()))
+    }
+
+    /// Whether a spots text shows enough open spots for `min_spots`; badges without a
+    /// count ("Register") pass, since there's no telling how many are left
+    pub fn has_min_spots(&self, spots: &str) -> bool {
+        let Some(min_spots) = self.min_spots else {
+            return true;
+        };
+        match Availability::parse(spots) {
+            Some(availability) => availability.open >= min_spots,
+            None => classify_spots(spots).is_open(),
+        }
+    }
+
+    /// Program name decorated with its emoji and color for console output
+    pub fn label(&self) -> String {
+        let name = match self.color {
+            Some(color) => format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), self.name),
+            None => self.name.to_string(),
+        };
+        match &self.emoji {
+            Some(emoji) => format!("{emoji} {name}"),
+            None => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    fn ansi_code(self) -> u8 {
+        match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::clock::Clock;
+    use crate::clock::tests::ManualClock;
+    use crate::parse::tests::spot;
+    use crate::state::tests::merged_session;
+
+    #[test]
+    fn backend_tables_are_notifiers_too() {
+        let config: Config = toml::from_str(r#"
+            program_ids = []
+
+            [signal]
+            api_url = "http://localhost:8080"
+            number = "+15195550100"
+            recipients = ["+15195550123"]
+
+            [teams]
+            webhook_url = "https://example.webhook.office.com/a"
+
+            [[notifiers]]
+            type = "issue_tracker"
+            api_url = "https://api.github.com"
+            repo = "my-club/court-log"
+            token = "ghp_x"
+            digest = true
+
+            [[notifiers]]
+            type = "whatsapp"
+            token = "EAAG"
+            phone_number_id = "1"
+            template = "court_update"
+            recipients = ["15195550123"]
+        "#).unwrap();
+
+        let notifiers: Vec<String> = config.notifiers().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            notifiers,
+            ["Signal +15195550123", "Teams webhook", "issues of my-club/court-log (digest)", "WhatsApp 15195550123"],
+        );
+    }
+
+    #[test]
+    fn zero_worker_threads_is_rejected_when_loading() {
+        let config: Config = toml::from_str("program_ids = []\n[runtime]\nworker_threads = 2").unwrap();
+        assert_eq!(config.runtime.worker_threads, NonZeroUsize::new(2));
+
+        let error = toml::from_str::<Config>("program_ids = []\n[runtime]\nworker_threads = 0").unwrap_err();
+        assert!(error.to_string().contains("worker_threads = 0"), "{error}");
+        assert!(error.to_string().contains("nonzero"), "{error}");
+    }
+
+    #[test]
+    fn notifiers_include_the_ntfy_shorthand() {
+        let config: Config = toml::from_str(r#"
+            interval_seconds = 60
+            ntfy_endpoint = "https://ntfy.sh/topic"
+
+            [[program_ids]]
+            id = "badminton"
+            name = "Badminton"
+            ntfy_endpoint = "https://ntfy.sh/roommate"
+
+            [[notifiers]]
+            type = "webhook"
+            url = "https://example.com/hook"
+
+            [[notifiers]]
+            type = "stdout"
+        "#).unwrap();
+
+        let notifiers: Vec<String> = config.notifiers().iter().map(ToString::to_string).collect();
+        assert_eq!(notifiers, ["ntfy https://ntfy.sh/topic", "webhook https://example.com/hook", "stdout"]);
+
+        // A program's own topic replaces the global one, the other notifiers stay
+        let badminton: Vec<String> =
+            config.program_notifiers(&config.program_ids[0]).iter().map(ToString::to_string).collect();
+        assert_eq!(badminton, ["ntfy https://ntfy.sh/roommate", "webhook https://example.com/hook", "stdout"]);
+    }
+
+    #[test]
+    fn merged_sessions_match_every_listing_program() {
+        let (_, _, session) = merged_session();
+        assert_eq!(session.programs_label(), "Beginner + Badminton");
+        assert_eq!(session.program_name.as_ref(), "Beginner");
+
+        let user = |program: &str| UserConfig {
+            name: "Sam".into(),
+            ntfy_endpoint: String::new(),
+            programs: vec![program.into()],
+            only_open: false,
+            quiet_hours: None,
+            map_links: None,
+        };
+        let now = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        assert!(user("badminton").wants(&session, now));
+        assert!(user("Badminton").wants(&session, now));
+        assert!(user("Beginner").wants(&session, now));
+        assert!(!user("Advanced").wants(&session, now));
+        let comparison: ComparisonConfig = toml::from_str("name = \"Courts\"\nprograms = [\"Badminton\"]").unwrap();
+        assert!(comparison.includes(&session));
+    }
+
+    #[test]
+    fn lookahead_and_date_range_limit_checked_dates() {
+        let config: Config = toml::from_str(r#"
+            interval_seconds = 60
+            program_ids = []
+            lookahead_days = 7
+            date_range = { start = "2025-03-01", end = "2025-03-31" }
+        "#).unwrap();
+        let date = |date: &str| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        let today = date("2025-03-14");
+
+        assert!(config.checks_date(today, today));
+        assert!(config.checks_date(date("2025-03-21"), today));
+        assert!(!config.checks_date(date("2025-03-22"), today));
+        assert!(!config.checks_date(date("2025-02-28"), date("2025-02-27")));
+    }
+
+    #[test]
+    fn quiet_hours_wrap_past_midnight() {
+        let user = UserConfig {
+            name: "Sam".into(),
+            ntfy_endpoint: String::new(),
+            programs: Vec::new(),
+            only_open: false,
+            quiet_hours: Some(TimeWindow::try_from("23:00-07:00".to_string()).unwrap()),
+            map_links: None,
+        };
+        let change = spot("Beginner", "2 spots left");
+
+        let clock = ManualClock::at("2025-03-14 22:59");
+        assert!(user.wants(&change, clock.now().time()));
+        clock.advance(Duration::from_secs(60));
+        assert!(!user.wants(&change, clock.now().time()));
+        clock.advance(Duration::from_secs(8 * 60 * 60));
+        assert!(user.wants(&change, clock.now().time()));
+    }
+
+    #[test]
+    fn notify_on_follows_the_direction_of_availability() {
+        assert!(NotifyOn::Increase.allows("0 spots left", "2 spots left"));
+        assert!(NotifyOn::Increase.allows("Full", "Register"));
+        assert!(!NotifyOn::Increase.allows("3 spots left", "2 spots left"));
+        assert!(!NotifyOn::Increase.allows("Register", "2 spots left"));
+        assert!(NotifyOn::Decrease.allows("1 spot left", "Waitlist"));
+        assert!(!NotifyOn::Decrease.allows("Full", "1 spot left"));
+        assert!(NotifyOn::Any.allows("Register", "2 spots left"));
+    }
+
+    #[test]
+    fn min_spots_needs_enough_open_spots() {
+        let program: ProgramConfig =
+            toml::from_str("id = \"1\"\nname = \"Doubles\"\nmin_spots = 2").unwrap();
+        assert!(program.has_min_spots("2 spots left"));
+        assert!(program.has_min_spots("3 of 12 spots left"));
+        assert!(!program.has_min_spots("1 spot left"));
+        assert!(!program.has_min_spots("Full"));
+        assert!(program.has_min_spots("Register"));
+    }
+
+    #[test]
+    fn program_days_and_time_range_filter_sessions() {
+        let program: ProgramConfig = toml::from_str(
+            "id = \"1\"\nname = \"Evenings\"\ndays = [\"Tue\", \"thursday\"]\ntime_range = \"17:00-21:00\"",
+        )
+        .unwrap();
+        let at = |date: &str| chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+        assert!(program.includes(at("2025-03-11 19:00")));
+        assert!(program.includes(at("2025-03-13 17:00")));
+        assert!(!program.includes(at("2025-03-13 21:00")));
+        assert!(!program.includes(at("2025-03-12 19:00")));
+    }
+}
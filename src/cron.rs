@@ -0,0 +1,207 @@
+//! Cron expressions for per-program check schedules, e.g. "*/2 7-23 * * *"
+
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+// Names allowed in the month and day-of-week fields
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// How far ahead to look for the next matching minute before giving up, e.g. on "0 0 31 2 *"
+const SEARCH_DAYS: i64 = 366 * 4;
+
+// A standard five-field cron expression (minute, hour, day of month, month, day of
+// week) as one bit set per field
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CronSchedule {
+    source: String,
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    // Sunday is bit 0
+    weekdays: u8,
+    // Whether each day field was left as `*`; when both are restricted, either matching will do
+    any_day_of_month: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Whether a check is due in the minute containing `at`
+    pub fn matches(&self, at: NaiveDateTime) -> bool {
+        self.matches_day(at) && self.hours & (1 << at.hour()) != 0 && self.minutes & (1 << at.minute()) != 0
+    }
+
+    fn matches_day(&self, at: NaiveDateTime) -> bool {
+        if self.months & (1 << at.month()) == 0 {
+            return false;
+        }
+        let day_of_month = self.days_of_month & (1 << at.day()) != 0;
+        let weekday = self.weekdays & (1 << at.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || weekday,
+        }
+    }
+
+    /// The first matching minute at or after `at`, in facility time
+    pub fn next_from(&self, at: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let start = at.naive_local().with_second(0)?.with_nanosecond(0)?;
+        let start = if start < at.naive_local() { start + Duration::minutes(1) } else { start };
+        let mut candidate = start;
+        while candidate - start < Duration::days(SEARCH_DAYS) {
+            if !self.matches_day(candidate) {
+                candidate = candidate.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << candidate.hour()) == 0 {
+                candidate = candidate.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << candidate.minute()) == 0 {
+                candidate += Duration::minutes(1);
+            } else if let Some(local) = at.timezone().from_local_datetime(&candidate).earliest() {
+                return Some(local);
+            } else {
+                // Skipped by a DST change
+                candidate += Duration::minutes(1);
+            }
+        }
+        None
+    }
+}
+
+impl std::fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for CronSchedule {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, weekday] = fields[..] else {
+            return Err(format!("invalid cron expression \"{value}\", expected 5 fields (minute hour day month weekday)"));
+        };
+        let field = |text: &str, min, max, names: &[&str]| {
+            parse_field(text, min, max, names).map_err(|e| format!("invalid cron expression \"{value}\": {e}"))
+        };
+        // 7 is Sunday too
+        let weekdays = field(weekday, 0, 7, &WEEKDAYS)?;
+        Ok(CronSchedule {
+            minutes: field(minute, 0, 59, &[])?,
+            hours: field(hour, 0, 23, &[])? as u32,
+            days_of_month: field(day_of_month, 1, 31, &[])? as u32,
+            months: field(month, 1, 12, &MONTHS)? as u16,
+            weekdays: ((weekdays | weekdays >> 7) & 0x7f) as u8,
+            any_day_of_month: day_of_month.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+            source: value,
+        })
+    }
+}
+
+/// One field's comma-separated list of `*`, `N`, `N-M` or names, each optionally
+/// stepped with `/S`, as a bit set
+fn parse_field(text: &str, min: u32, max: u32, names: &[&str]) -> std::result::Result<u64, String> {
+    let value = |part: &str| -> std::result::Result<u32, String> {
+        let lower = part.to_ascii_lowercase();
+        let number = match names.iter().position(|name| *name == lower) {
+            // Month names count from 1, weekday names from 0 (Sunday)
+            Some(index) => index as u32 + min,
+            None => part.parse().map_err(|_| format!("\"{part}\" isn't a number"))?,
+        };
+        if (min..=max).contains(&number) {
+            Ok(number)
+        } else {
+            Err(format!("{number} is outside {min}-{max}"))
+        }
+    };
+
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse().ok().filter(|&step| step > 0);
+                (range, step.ok_or(format!("bad step in \"{part}\""))?)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // "5/15" runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("range \"{range}\" runs backwards"));
+        }
+        for number in (start..=end).step_by(step) {
+            bits |= 1 << number;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FACILITY_TZ;
+    use crate::config::ProgramConfig;
+
+    #[test]
+    fn cron_schedules_skip_the_night() {
+        let program: ProgramConfig = toml::from_str(r#"
+            id = "p"
+            name = "Beginner"
+            cron = "*/2 7-23 * * mon-fri"
+        "#).unwrap();
+        let cron = program.cron.unwrap();
+        let at = |time: &str| {
+            chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap().and_local_timezone(FACILITY_TZ).unwrap()
+        };
+
+        // 2025-03-14 is a Friday
+        assert!(cron.matches(at("2025-03-14 07:02").naive_local()));
+        assert!(!cron.matches(at("2025-03-14 07:03").naive_local()));
+        assert_eq!(cron.next_from(at("2025-03-14 07:03")), Some(at("2025-03-14 07:04")));
+        // Nothing overnight or on the weekend, so the next check is Monday morning
+        assert_eq!(cron.next_from(at("2025-03-14 23:59")), Some(at("2025-03-17 07:00")));
+
+        // Either day field matching is enough when both are restricted, as in cron
+        let cron = CronSchedule::try_from("0 9 1 * sun".to_string()).unwrap();
+        assert!(cron.matches(at("2025-03-16 09:00").naive_local()));
+        assert!(cron.matches(at("2025-04-01 09:00").naive_local()));
+        assert!(!cron.matches(at("2025-04-02 09:00").naive_local()));
+
+        assert!(CronSchedule::try_from("*/2 7-23 * *".to_string()).is_err());
+        assert!(CronSchedule::try_from("0 25 * * *".to_string()).is_err());
+        assert!(CronSchedule::try_from("0 9 31 2 *".to_string()).unwrap().next_from(at("2025-03-14 07:00")).is_none());
+    }
+
+    #[test]
+    fn empty_and_invalid_fields_are_rejected() {
+        let error = |expression: &str| CronSchedule::try_from(expression.to_string()).unwrap_err();
+        assert!(error("").contains("expected 5 fields"));
+        assert!(error("   ").contains("expected 5 fields"));
+        assert!(error("0 9 * * * *").contains("expected 5 fields"));
+        assert!(error("0 9 1,,15 * *").contains("\"\" isn't a number"));
+        assert!(error("0 9- * * *").contains("\"\" isn't a number"));
+        assert!(error("*/0 * * * *").contains("bad step in \"*/0\""));
+        assert!(error("*/ * * * *").contains("bad step"));
+        assert!(error("0 23-7 * * *").contains("runs backwards"));
+        assert!(error("60 * * * *").contains("60 is outside 0-59"));
+        assert!(error("0 9 0 * *").contains("0 is outside 1-31"));
+        assert!(error("0 9 * * funday").contains("\"funday\" isn't a number"));
+
+        // Both 0 and 7 are Sunday
+        let sunday = chrono::NaiveDateTime::parse_from_str("2025-03-16 09:00", "%Y-%m-%d %H:%M").unwrap();
+        assert!(CronSchedule::try_from("0 9 * * 7".to_string()).unwrap().matches(sunday));
+        assert!(CronSchedule::try_from("0 9 * * 0".to_string()).unwrap().matches(sunday));
+    }
+}
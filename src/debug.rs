@@ -0,0 +1,91 @@
+//! A notifier for testing: keeps notifications in memory and/or writes them to a file,
+//! with optional latency and failures to see how the rest of the monitor copes
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::config::DebugConfig;
+use crate::error::{Error, Result};
+use crate::notify::{Notification, Notifier};
+use crate::parse::SpotInfo;
+
+// What each named buffer has seen so far
+#[derive(Default)]
+struct Buffer {
+    attempts: u64,
+    delivered: Vec<Notification>,
+}
+
+static BUFFERS: LazyLock<Mutex<HashMap<String, Buffer>>> = LazyLock::new(Default::default);
+
+/// Notifications delivered to the named buffer so far, oldest first
+pub fn recorded(buffer: &str) -> Vec<Notification> {
+    BUFFERS
+        .lock()
+        .unwrap()
+        .get(buffer)
+        .map(|buffer| buffer.delivered.clone())
+        .unwrap_or_default()
+}
+
+/// Forget everything the named buffer has seen, including its failure count
+pub fn clear(buffer: &str) {
+    BUFFERS.lock().unwrap().remove(buffer);
+}
+
+impl Notifier for DebugConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        if self.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.latency_ms)).await;
+        }
+
+        {
+            let mut buffers = BUFFERS.lock().unwrap();
+            let buffer = buffers.entry(self.buffer.clone()).or_default();
+            buffer.attempts += 1;
+            if self.fail_every.is_some_and(|every| buffer.attempts.is_multiple_of(u64::from(every))) {
+                return Err(Error::Notify(format!("injected failure on attempt {}", buffer.attempts)));
+            }
+            buffer.delivered.push(notification.clone());
+        }
+
+        if let Some(path) = &self.path {
+            let line = serde_json::json!({
+                "title": notification.title,
+                "message": notification.message,
+                "tags": notification.tags,
+                "priority": notification.priority.map(|priority| priority.as_str()),
+                "availability": notification.spot.as_ref().and_then(SpotInfo::availability),
+                "observed_at": notification.spot.as_ref().and_then(|spot| spot.observed_at),
+                "stale": notification.spot.as_ref().is_some_and(|spot| spot.stale),
+            });
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NotifierConfig;
+
+    #[tokio::test]
+    async fn debug_notifier_records_and_fails_on_schedule() {
+        use crate::notify::Notifier;
+        let notifier: NotifierConfig =
+            toml::from_str("type = \"debug\"\nbuffer = \"flaky\"\nfail_every = 2").unwrap();
+        for title in ["first", "second", "third"] {
+            let result = notifier.send(&Notification::new(title, "")).await;
+            assert_eq!(result.is_err(), title == "second");
+        }
+        let titles: Vec<String> = recorded("flaky").into_iter().map(|n| n.title).collect();
+        assert_eq!(titles, ["first", "third"]);
+        clear("flaky");
+        assert!(recorded("flaky").is_empty());
+    }
+}
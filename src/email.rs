@@ -0,0 +1,41 @@
+//! SMTP delivery, only compiled with the `email` feature
+
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::{EmailConfig, EmailTls};
+use crate::error::{Error, Result};
+use crate::notify::Notification;
+
+/// Email a notification to every configured recipient
+pub async fn send(email: &EmailConfig, notification: &Notification) -> Result<()> {
+    let mut transport = match email.tls {
+        EmailTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.server).map_err(Error::notify)?,
+        EmailTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&email.server).map_err(Error::notify)?,
+        EmailTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&email.server),
+    };
+    if let Some(port) = email.port {
+        transport = transport.port(port);
+    }
+    if let Some(username) = &email.username {
+        transport = transport.credentials(Credentials::new(
+            username.clone(),
+            email.password.clone().unwrap_or_default(),
+        ));
+    }
+
+    let mut message = Message::builder()
+        .from(email.from.parse().map_err(Error::notify)?)
+        .subject(&notification.title)
+        .header(ContentType::TEXT_PLAIN);
+    for to in &email.to {
+        message = message.to(to.parse().map_err(Error::notify)?);
+    }
+    let message = message.body(notification.message.clone()).map_err(Error::notify)?;
+
+    transport.build().send(message).await.map_err(Error::notify)?;
+    say!("✅ Email sent to {}", email.to.join(", "));
+
+    Ok(())
+}
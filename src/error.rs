@@ -0,0 +1,97 @@
+//! Error taxonomy shared by fetching, parsing, configuration and notification code
+
+use std::fmt::Display;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The site couldn't be reached or the transfer failed
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+
+    /// The site responded with something we couldn't make sense of
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    /// The site answered, but not with a page we should parse (wrong content
+    /// type, redirect loop, redirect off-site)
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("config error: {0}")]
+    Config(String),
+
+    /// The site or a notification backend answered with an error status
+    #[error("HTTP {status} from {url}")]
+    Status { status: reqwest::StatusCode, url: String },
+
+    /// The site or a notification backend answered 429 Too Many Requests, maybe saying
+    /// when to come back
+    #[error("rate limited by {url}")]
+    RateLimited { url: String, retry_after: Option<std::time::Duration> },
+
+    /// A notification backend couldn't be reached or rejected the message
+    #[error("notify error: {0}")]
+    Notify(String),
+
+    /// The site refused our request because it wants us to log in
+    #[error("auth error: {0}")]
+    Auth(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[cfg(feature = "browser")]
+    #[error("browser error: {0}")]
+    Browser(#[from] chromiumoxide::error::CdpError),
+}
+
+impl Error {
+    /// Wrap a failure that happened while delivering a notification
+    pub fn notify(error: impl Display) -> Self {
+        Error::Notify(error.to_string())
+    }
+
+    /// Short name of the kind of failure, for logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Network(_) => "network",
+            Error::Parse(_) => "parse",
+            Error::UnexpectedResponse(_) => "unexpected response",
+            Error::Status { .. } => "HTTP status",
+            Error::RateLimited { .. } => "rate limit",
+            Error::Config(_) => "config",
+            Error::Notify(_) => "notify",
+            Error::Auth(_) => "auth",
+            Error::Io(_) => "I/O",
+            #[cfg(feature = "browser")]
+            Error::Browser(_) => "browser",
+        }
+    }
+
+    /// Whether trying again shortly might succeed: timeouts, dropped connections, server
+    /// errors, rate limiting and unreachable notification backends, but never bad pages or config
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            Error::Status { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::REQUEST_TIMEOUT
+            }
+            Error::RateLimited { .. } | Error::Notify(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::Config(error.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,129 @@
+//! Registry of the facilities sessions are held at, keyed by the site's Location text,
+//! and map links to them
+
+use reqwest::Url;
+use std::sync::LazyLock;
+
+use crate::config::{Config, FacilityConfig, MapProvider};
+use crate::parse::SpotInfo;
+
+// Facilities the site is known to list; `[facilities]` in the config adds to or overrides these
+static BUILT_IN: LazyLock<Vec<(&'static str, FacilityConfig)>> = LazyLock::new(|| {
+    vec![
+        ("PAC", FacilityConfig {
+            name: Some("Physical Activities Complex".into()),
+            address: Some("200 University Ave W, Waterloo, ON N2L 3G1".into()),
+            coordinates: Some([43.4722, -80.5460]),
+            outdoor: false,
+            map_url: None,
+        }),
+        ("CIF", FacilityConfig {
+            name: Some("Columbia Icefield".into()),
+            address: Some("200 Columbia St W, Waterloo, ON N2L 3L3".into()),
+            coordinates: Some([43.4787, -80.5529]),
+            outdoor: false,
+            map_url: None,
+        }),
+    ]
+});
+
+impl Config {
+    /// The facility a session location refers to: an exact match first, otherwise the
+    /// longest registry key the location contains (e.g. "PAC" for "PAC - Gym 2")
+    pub fn facility(&self, location: &str) -> Option<&FacilityConfig> {
+        let configured = self.facilities.iter().map(|(key, facility)| (key.as_str(), facility));
+        let built_in = BUILT_IN.iter().map(|(key, facility)| (*key, facility));
+        let entries: Vec<(&str, &FacilityConfig)> = configured.chain(built_in).collect();
+
+        let location = location.to_lowercase();
+        if let Some((_, facility)) = entries.iter().find(|(key, _)| key.to_lowercase() == location) {
+            return Some(facility);
+        }
+        // Earlier entries (the config's) win ties
+        entries
+            .iter()
+            .filter(|(key, _)| !key.is_empty() && location.contains(&key.to_lowercase()))
+            .rev()
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, facility)| *facility)
+    }
+}
+
+/// Link to the facility in the chosen map app: the facility's own `map_url` when it has
+/// one, otherwise a search for its coordinates, address or the raw location
+pub fn map_link(provider: MapProvider, spot: &SpotInfo, facility: Option<&FacilityConfig>) -> Option<String> {
+    if let Some(url) = facility.and_then(|facility| facility.map_url.clone()) {
+        return Some(url);
+    }
+    let query = match facility {
+        Some(FacilityConfig { coordinates: Some([latitude, longitude]), .. }) => format!("{latitude},{longitude}"),
+        Some(FacilityConfig { address: Some(address), .. }) => address.clone(),
+        _ if spot.location.is_empty() => return None,
+        _ => spot.location.to_string(),
+    };
+    let url = match provider {
+        MapProvider::Google => {
+            Url::parse_with_params("https://www.google.com/maps/search/", [("api", "1"), ("query", query.as_str())])
+        }
+        MapProvider::Apple => Url::parse_with_params("https://maps.apple.com/", [("q", query.as_str())]),
+    };
+    url.ok().map(String::from)
+}
+
+/// A "📍 <link>" line for an alert, or nothing when map links are off
+pub fn map_note(provider: Option<MapProvider>, spot: &SpotInfo, facility: Option<&FacilityConfig>) -> String {
+    provider
+        .and_then(|provider| map_link(provider, spot, facility))
+        .map(|link| format!("\n📍 {link}"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn facilities_match_locations_and_can_be_overridden() {
+        let config: Config = toml::from_str(r#"
+            interval_seconds = 60
+            program_ids = []
+
+            [facilities."Warrior Courts"]
+            coordinates = [43.4750, -80.5480]
+            outdoor = true
+
+            [facilities.CIF]
+            name = "Columbia Icefield (renovated)"
+        "#).unwrap();
+
+        let pac = config.facility("PAC - Gym 2").unwrap();
+        assert_eq!(pac.name.as_deref(), Some("Physical Activities Complex"));
+        assert_eq!(config.facility("cif").unwrap().name.as_deref(), Some("Columbia Icefield (renovated)"));
+        assert!(config.facility("Warrior Courts").unwrap().outdoor);
+        assert!(config.facility("Village 1").is_none());
+    }
+
+    #[test]
+    fn map_links_prefer_the_facility_url_then_coordinates() {
+        use crate::config::MapProvider;
+        let config: Config = toml::from_str(r#"
+            interval_seconds = 60
+            program_ids = []
+
+            [facilities.Courts]
+            map_url = "https://maps.example.com/courts"
+        "#).unwrap();
+        let mut session = spot("Beginner", "2 spots left");
+        let link = |session: &SpotInfo, provider| {
+            map_link(provider, session, config.facility(&session.location))
+        };
+
+        assert_eq!(link(&session, MapProvider::Google).unwrap(), "https://www.google.com/maps/search/?api=1&query=43.4722%2C-80.546");
+        session.location = "Courts".into();
+        assert_eq!(link(&session, MapProvider::Apple).unwrap(), "https://maps.example.com/courts");
+        session.location = "Village Green".into();
+        assert_eq!(link(&session, MapProvider::Apple).unwrap(), "https://maps.apple.com/?q=Village+Green");
+        assert_eq!(map_note(None, &session, None), "");
+    }
+}
@@ -0,0 +1,148 @@
+//! Following other instances through their public mirrors, so a friend's monitor
+//! covering other programs feeds this one's notifiers
+
+use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+
+use crate::config::PeerConfig;
+use crate::error::Result;
+use crate::mirror::{MirroredSession, Snapshot};
+use crate::notify::{Notification, Priority};
+use crate::parse::SpotInfo;
+
+// What identifies a session across instances: instances key sessions by appointment
+// ID, which mirrors don't publish
+pub type SessionKey = (String, String, String, String, String);
+
+pub fn key(session: &MirroredSession) -> SessionKey {
+    (
+        session.program.clone(),
+        session.product.clone(),
+        session.date.clone(),
+        session.time.clone(),
+        session.location.clone(),
+    )
+}
+
+// The peers to follow, with the sessions each published last time
+#[derive(Debug)]
+pub struct Peers {
+    peers: Vec<PeerConfig>,
+    seen: HashMap<String, HashMap<SessionKey, MirroredSession>>,
+}
+
+impl Peers {
+    pub fn new(peers: Vec<PeerConfig>) -> Self {
+        Peers { peers, seen: HashMap::new() }
+    }
+
+    /// Fetch every peer's snapshot and turn what changed since the last fetch into
+    /// notifications, leaving out sessions this instance tracks itself. A peer's first
+    /// snapshot only sets the baseline.
+    pub async fn poll(&mut self, client: &Client, own: &HashMap<String, SpotInfo>) -> Vec<Notification> {
+        let tracked: HashSet<SessionKey> = own
+            .values()
+            .map(|spot| {
+                (
+                    spot.programs_label(),
+                    spot.product_name.to_string(),
+                    spot.date.to_string(),
+                    spot.time.to_string(),
+                    spot.location.to_string(),
+                )
+            })
+            .collect();
+
+        let mut notifications = Vec::new();
+        for peer in &self.peers {
+            let snapshot = match fetch(client, &peer.url).await {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    eprintln!("Failed to fetch availability from {}: {}", peer.name, e);
+                    continue;
+                }
+            };
+            let current: HashMap<SessionKey, MirroredSession> =
+                snapshot.sessions.into_iter().map(|session| (key(&session), session)).collect();
+            if let Some(previous) = self.seen.get(&peer.name) {
+                let changed = changes(&peer.name, previous, &current);
+                notifications.extend(changed.into_iter().filter(|(key, _)| !tracked.contains(key)).map(|(_, n)| n));
+            }
+            self.seen.insert(peer.name.clone(), current);
+        }
+        notifications
+    }
+}
+
+async fn fetch(client: &Client, url: &str) -> Result<Snapshot> {
+    Ok(client.get(url).send().await?.error_for_status()?.json().await?)
+}
+
+/// Notifications for a peer's sessions whose spots changed, and for new ones that are open
+pub fn changes(
+    peer: &str,
+    previous: &HashMap<SessionKey, MirroredSession>,
+    current: &HashMap<SessionKey, MirroredSession>,
+) -> Vec<(SessionKey, Notification)> {
+    let mut changed: Vec<(&SessionKey, &MirroredSession)> = current.iter().collect();
+    changed.sort_by(|a, b| a.0.cmp(b.0));
+    changed
+        .into_iter()
+        .filter_map(|(key, session)| {
+            let notification = match previous.get(key) {
+                // A stale count says nothing new about the session
+                Some(before) if before.spots != session.spots && !session.stale => Notification {
+                    previous_spots: Some(before.spots.clone()),
+                    priority: Some(Priority::for_change(&before.spots, &session.spots)),
+                    ..Notification::new(
+                        format!("Spot change: {} (via {})", session.product, peer),
+                        format!("{} ({}) on {} @ {}: {} → {}\n{}",
+                            session.program, session.product, session.date, session.time,
+                            before.spots, session.spots, session.url),
+                    )
+                },
+                None if session.open => Notification {
+                    priority: Some(Priority::High),
+                    ..Notification::new(
+                        format!("Spot available: {} (via {})", session.product, peer),
+                        format!("{} ({}) on {} @ {}: {}\n{}",
+                            session.program, session.product, session.date, session.time,
+                            session.spots, session.url),
+                    )
+                },
+                _ => return None,
+            };
+            Some((key.clone(), notification))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn peer_changes_become_notifications() {
+        let now = chrono::NaiveDateTime::parse_from_str("2025-03-14 12:00", "%Y-%m-%d %H:%M").unwrap();
+        let published = |spots: &[(&str, &str)]| {
+            let spots = spots.iter().map(|(time, spots)| {
+                let mut session = spot("Badminton", spots);
+                session.time = (*time).into();
+                (time.to_string(), session)
+            });
+            let snapshot = Snapshot::new(&spots.collect(), now);
+            snapshot.sessions.into_iter().map(|session| (key(&session), session)).collect()
+        };
+        let before = published(&[("17:00:00", "Full"), ("19:00:00", "Full")]);
+        let after = published(&[("17:00:00", "Full"), ("19:00:00", "1 spot left"), ("21:00:00", "3 spots left")]);
+
+        let notifications: Vec<Notification> =
+            changes("Sam", &before, &after).into_iter().map(|(_, notification)| notification).collect();
+        let titles: Vec<&str> = notifications.iter().map(|notification| notification.title.as_str()).collect();
+        assert_eq!(titles, ["Spot change: Drop-in (via Sam)", "Spot available: Drop-in (via Sam)"]);
+        assert!(notifications[0].message.contains("Full → 1 spot left"));
+        assert_eq!(notifications[0].priority, Some(Priority::High));
+        assert!(changes("Sam", &after, &after).is_empty());
+    }
+}
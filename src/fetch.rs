@@ -0,0 +1,549 @@
+//! Requests against the Warrior site
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "browser")]
+use crate::browser;
+use crate::clock::{Clock, SystemClock, check_clock_skew};
+use crate::config::{Config, ProgramConfig, RateLimitConfig};
+use crate::error::{Error, Result};
+use crate::parse::{
+    Appointment, Calendar, InstanceDetails, SchemaDrift, SpotInfo, facility_datetime, instance_appointment_ids, intern,
+    membership_allowed, parse_initial, parse_instance, reconcile, sanitize_text,
+};
+use crate::rate_limit;
+use crate::retry::with_retry;
+
+pub const SITE_URL: &str = "https://warrior.uwaterloo.ca";
+pub const GET_PATH: &str = "/Program/GetProgramInstances";
+pub const FILTER_PATH: &str = "/Program/FilterProgramInstances";
+
+/// Page where a program's sessions are listed and registered for
+pub fn registration_url(program_id: &str) -> String {
+    calendar_url(SITE_URL, program_id)
+}
+
+/// The same page on the site at `site_url`
+fn calendar_url(site_url: &str, program_id: &str) -> String {
+    format!("{}{GET_PATH}?programID={program_id}", site_url.trim_end_matches('/'))
+}
+
+// Redirects followed before giving up on a request
+const MAX_REDIRECTS: usize = 5;
+
+/// HTTP client set up to look like the site's own calendar page
+pub fn build_client() -> Result<Client> {
+    let client = Client::builder()
+        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:138.0)")
+        .default_headers({
+            use reqwest::header::HeaderValue;
+            let mut h = reqwest::header::HeaderMap::new();
+            h.insert("Accept", HeaderValue::from_static("*/*"));
+            h.insert(
+                "Content-Type",
+                HeaderValue::from_static("application/x-www-form-urlencoded; charset=UTF-8"),
+            );
+            h.insert("X-Requested-With", HeaderValue::from_static("XMLHttpRequest"));
+            h.insert("Origin", HeaderValue::from_static("https://warrior.uwaterloo.ca"));
+            h
+        })
+        // Hand back redirects to a login page or another site instead of following them,
+        // so check_response can say what happened
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            let off_site = attempt
+                .previous()
+                .first()
+                .is_some_and(|first| first.host_str() != attempt.url().host_str());
+            if attempt.previous().len() > MAX_REDIRECTS || off_site || is_login_url(attempt.url()) {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()?;
+    Ok(client)
+}
+
+/// Default fields carried over in each appointment payload
+fn default_fields() -> HashMap<&'static str, &'static str> {
+    [
+        ("RecurrenceInfo", ""),
+        ("AppointmentType", "0"),
+        ("Subject", ""),
+        ("AllDay", "false"),
+        ("ResourceId", ""),
+        ("Status", "0"),
+        ("ProductId", "00000000-0000-0000-0000-000000000000"),
+        ("ProgramDescription", ""),
+        ("ProgramInstanceId", "00000000-0000-0000-0000-000000000000"),
+        ("NumberRegistered", "0"),
+        ("NumberOnWaitlist", "0"),
+        ("ClassSize", "12"),
+        ("PortalURL", ""),
+        ("InstructorFirstNameLastInitial", ""),
+        ("IsInstructor", "false"),
+        ("InstructorId", "00000000-0000-0000-0000-000000000000"),
+        ("IsRecurring", "false"),
+    ]
+    .into()
+}
+
+pub async fn check_program(
+    client: &Client, 
+    config: &Config,
+    program: &ProgramConfig,
+) -> Result<HashMap<String, SpotInfo>> {
+    let program_id = &*program.id;
+
+    // Fetch the data for this program
+    let (mut appts, dates) = catalog(client, config, program_id).await?;
+    report_schema_drift(program, &SchemaDrift::of(&appts));
+    // Nothing can be asked about a session without its ID
+    appts.retain(|appt| !appt.id.trim().is_empty());
+    let mut current_spots = HashMap::new();
+    let observed_at = SystemClock.now().naive_local();
+    let today = observed_at.date();
+    let calendar = reconcile(&appts, &dates);
+    report_consistency(program, &calendar);
+    
+    for (date, day_appts) in calendar.days {
+        // Dates outside the lookahead window or date range aren't worth a request
+        if !config.checks_date(date, today) {
+            continue;
+        }
+
+        // A program can hold several sessions on the same date, e.g. at 6pm and 8pm;
+        // sessions outside the program's days and hours are never tracked
+        let day_appts: Vec<(&Appointment, NaiveDateTime)> = day_appts
+            .into_iter()
+            .filter(|(_, start)| program.includes(*start))
+            .collect();
+        if day_appts.is_empty() {
+            continue;
+        }
+
+        // All of the date's sessions come back from one request
+        let batch: Vec<&Appointment> = day_appts.iter().map(|(appt, _)| *appt).collect();
+        let all_details = match fetch_spots(client, config, program_id, &batch, date).await {
+            Ok(all_details) => all_details,
+            Err(e) => {
+                // Maybe the cached catalog has gone out of date; start afresh next time
+                forget_catalog(config, program_id);
+                return Err(e);
+            }
+        };
+        for ((appt, start), details) in day_appts.into_iter().zip(all_details) {
+            if !membership_allowed(details.membership.as_deref(), &config.memberships) {
+                continue;
+            }
+
+            let date = date.format("%Y-%m-%d").to_string();
+            let time = start.format("%H:%M:%S").to_string();
+            let end_time = facility_datetime(&appt.end_date)
+                .map(|end| end.format("%H:%M:%S").to_string())
+                .unwrap_or_default();
+            let key = format!("{}-{}-{}-{}", program_id, date, time, appt.id);
+            let identity = format!("{}-{}-{}-{}", program_id, date, time, appt.product_name);
+            
+            current_spots.insert(key, SpotInfo {
+                identity,
+                program_id: program.id.clone(),
+                program_name: program.name.clone(),
+                product_name: intern(&sanitize_text(&appt.product_name)),
+                location: intern(&sanitize_text(&appt.location)),
+                date: intern(&date),
+                time: intern(&time),
+                end_time: intern(&end_time),
+                spots: details.spots,
+                price: details.price,
+                membership: details.membership.as_deref().map(intern),
+                observed_at: Some(observed_at),
+                stale: false,
+                portal_url: appt.portal_url.clone().filter(|url| !url.trim().is_empty()),
+                also_listed_under: Vec::new(),
+            });
+        }
+    }
+    
+    Ok(current_spots)
+}
+
+// A program's calendar page (its sessions, their products, locations and times) as last
+// fetched, by site and program ID
+struct CachedCatalog {
+    fetched_at: Instant,
+    appts: Vec<Appointment>,
+    dates: Vec<String>,
+}
+
+type CatalogKey = (String, Arc<str>);
+
+static CATALOGS: LazyLock<Mutex<HashMap<CatalogKey, CachedCatalog>>> = LazyLock::new(Default::default);
+
+/// The program's calendar page, from the cache while it's younger than
+/// `catalog_ttl_seconds`; spots always come fresh from the per-date requests
+async fn catalog(client: &Client, config: &Config, program_id: &str) -> Result<(Vec<Appointment>, Vec<String>)> {
+    let ttl = Duration::from_secs(config.catalog_ttl_seconds);
+    let key = (config.site_url.clone(), Arc::from(program_id));
+    if !ttl.is_zero() {
+        let cached = CATALOGS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(catalog) = cached.get(&key)
+            && catalog.fetched_at.elapsed() < ttl
+        {
+            return Ok((catalog.appts.clone(), catalog.dates.clone()));
+        }
+    }
+
+    let (appts, dates) = fetch_initial(client, config, program_id).await?;
+    if !ttl.is_zero() {
+        let catalog = CachedCatalog { fetched_at: Instant::now(), appts: appts.clone(), dates: dates.clone() };
+        CATALOGS.lock().unwrap_or_else(|e| e.into_inner()).insert(key, catalog);
+    }
+    Ok((appts, dates))
+}
+
+fn forget_catalog(config: &Config, program_id: &str) {
+    CATALOGS.lock().unwrap_or_else(|e| e.into_inner()).remove(&(config.site_url.clone(), Arc::from(program_id)));
+}
+
+// Last calendar inconsistency reported per program ID, so each is only warned about once
+static CALENDAR_PROBLEMS: LazyLock<Mutex<HashMap<Arc<str>, String>>> = LazyLock::new(Default::default);
+
+/// Warn when a program's hdnDates and ApptInfo start or stop disagreeing
+fn report_consistency(program: &ProgramConfig, calendar: &Calendar) {
+    let problems = calendar.describe();
+    let mut reported = CALENDAR_PROBLEMS.lock().unwrap_or_else(|e| e.into_inner());
+    let previous = reported.get(&program.id).cloned().unwrap_or_default();
+    if problems == previous {
+        return;
+    }
+    if calendar.is_consistent() {
+        say!("✅ {}: calendar dates and sessions agree again", program.name);
+        reported.remove(&program.id);
+    } else {
+        say!("⚠️ {}: calendar dates and sessions disagree, {}", program.name, problems);
+        reported.insert(program.id.clone(), problems);
+    }
+}
+
+// Schema drift last reported for each program, so it's only logged when it changes
+static SCHEMA_DRIFT: LazyLock<Mutex<HashMap<Arc<str>, SchemaDrift>>> = LazyLock::new(Default::default);
+
+/// Warn when a program's ApptInfo starts or stops straying from the known schema
+fn report_schema_drift(program: &ProgramConfig, drift: &SchemaDrift) {
+    let mut reported = SCHEMA_DRIFT.lock().unwrap_or_else(|e| e.into_inner());
+    if reported.get(&program.id).unwrap_or(&SchemaDrift::default()) == drift {
+        return;
+    }
+    if drift.is_empty() {
+        say!("✅ {}: calendar sessions match the known schema again", program.name);
+        reported.remove(&program.id);
+    } else {
+        say!("⚠️ {}: calendar sessions changed shape, {}", program.name, drift.describe());
+        reported.insert(program.id.clone(), drift.clone());
+    }
+}
+
+/// Grab a PNG screenshot of a program's calendar page
+pub async fn capture_calendar(program_id: &str) -> Result<Vec<u8>> {
+    #[cfg(feature = "browser")]
+    {
+        browser::screenshot(&registration_url(program_id)).await
+    }
+    #[cfg(not(feature = "browser"))]
+    {
+        let _ = program_id;
+        Err(Error::Config("screenshots require building with the `browser` feature".into()))
+    }
+}
+
+/// Capture a program's calendar and write it to `dir` for later inspection
+pub async fn save_screenshot(dir: &str, program_id: &str) {
+    let result = async {
+        let png = capture_calendar(program_id).await?;
+        fs::create_dir_all(dir)?;
+        let timestamp = chrono::Utc::now().timestamp();
+        let path = std::path::Path::new(dir).join(format!("{program_id}-{timestamp}.png"));
+        fs::write(&path, png)?;
+        Ok::<_, Error>(path)
+    }
+    .await;
+
+    match result {
+        Ok(path) => say!("📸 Saved calendar screenshot to {}", path.display()),
+        Err(e) => eprintln!("Failed to save screenshot for {}: {}", program_id, e),
+    }
+}
+
+pub async fn fetch_initial(
+    client: &Client,
+    config: &Config,
+    program_id: &str,
+) -> Result<(Vec<Appointment>, Vec<String>)> {
+    let res = with_retry(&config.retry, &format!("Fetching program {program_id}"), || async {
+        rate_limit::acquire(&config.rate_limit).await;
+        let response = client
+            .get(format!("{}{GET_PATH}", config.site_url.trim_end_matches('/')))
+            .query(&[("programID", program_id)])
+            .send()
+            .await?;
+        check_clock_skew(&response, Duration::from_secs(config.max_clock_skew_seconds));
+        let response = check_response(response, &config.rate_limit)?;
+        let html = read_body(response, config.max_response_bytes).await?;
+        parse_initial(&html)
+    })
+    .await;
+
+    // Only a page we couldn't read is worth rendering; being rate limited, refused or
+    // unreachable would go no better for the browser
+    match res {
+        Err(e @ (Error::Parse(_) | Error::UnexpectedResponse(_))) if config.browser_fallback => {
+            eprintln!("Plain fetch failed for {} ({}), rendering with headless browser", program_id, e);
+            rate_limit::acquire(&config.rate_limit).await;
+            let html = render_calendar(&calendar_url(&config.site_url, program_id)).await?;
+            parse_initial(&html)
+        }
+        res => res,
+    }
+}
+
+/// Make sure a site response is an HTML page worth parsing, classifying the ways it isn't
+fn check_response(response: reqwest::Response, limits: &RateLimitConfig) -> Result<reqwest::Response> {
+    let url = response.url().clone();
+    let status = response.status();
+
+    // Everyone backs off for as long as the site asks, not just this request
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        if let Some(retry_after) = retry_after {
+            rate_limit::pause(limits, retry_after);
+        }
+        return Err(Error::RateLimited { url: url.to_string(), retry_after });
+    }
+
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(Error::Auth(format!("{} responded {}", url, status)));
+    }
+    if status.is_server_error() || status == reqwest::StatusCode::REQUEST_TIMEOUT {
+        return Err(Error::Status { status, url: url.to_string() });
+    }
+
+    // Only redirects the client policy refused to follow end up here
+    if status.is_redirection() {
+        let Some(location) = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| url.join(value).ok())
+        else {
+            return Err(Error::UnexpectedResponse(format!("{} responded {} without a location", url, status)));
+        };
+        return Err(if is_login_url(&location) {
+            Error::Auth(format!("{} redirected to the login page at {}", url, location))
+        } else if location.host_str() != url.host_str() {
+            Error::UnexpectedResponse(format!("{} redirected off-site to {}", url, location))
+        } else {
+            Error::UnexpectedResponse(format!("{} redirected more than {} times", url, MAX_REDIRECTS))
+        });
+    }
+
+    // A missing content type is given the benefit of the doubt
+    if let Some(content_type) = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        if mime != "text/html" && mime != "application/xhtml+xml" {
+            return Err(Error::UnexpectedResponse(format!("expected HTML from {}, got {}", url, mime)));
+        }
+    }
+
+    Ok(response)
+}
+
+/// A Retry-After header value, either a number of seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Whether a URL looks like a sign-in page rather than site content
+fn is_login_url(url: &reqwest::Url) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    ["login", "logon", "signin", "sign-in", "sso", "auth"]
+        .iter()
+        .any(|marker| path.contains(marker))
+}
+
+/// Read a response body, giving up as soon as it grows past `max_bytes`
+async fn read_body(mut response: reqwest::Response, max_bytes: usize) -> Result<String> {
+    let url = response.url().clone();
+    let too_large = || Error::Parse(format!("response from {} exceeded {} bytes", url, max_bytes));
+
+    // Don't even start when the server announces a body over budget
+    if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Render the calendar page at `url` in a headless browser and return the resulting HTML
+async fn render_calendar(url: &str) -> Result<String> {
+    #[cfg(feature = "browser")]
+    {
+        browser::render(url).await
+    }
+    #[cfg(not(feature = "browser"))]
+    {
+        let _ = url;
+        Err(Error::Config("browser fallback requires building with the `browser` feature".into()))
+    }
+}
+
+/// Fetch the instance cards of several appointments on one date with a single request,
+/// returning their details in the same order
+pub async fn fetch_spots(
+    client: &Client,
+    config: &Config,
+    program_id: &str,
+    appts: &[&Appointment],
+    date: NaiveDate,
+) -> Result<Vec<InstanceDetails>> {
+    // Build form data, one `appointments[i]` entry per appointment
+    let mut form = HashMap::new();
+    for (index, appt) in appts.iter().enumerate() {
+        let prefix = format!("appointments[{index}]");
+        form.insert(format!("{prefix}[ID]"), appt.id.clone());
+        form.insert(format!("{prefix}[StartDate]"), appt.start_date.clone());
+        form.insert(format!("{prefix}[EndDate]"), appt.end_date.clone());
+        form.insert(format!("{prefix}[Location]"), appt.location.clone());
+        form.insert(
+            format!("{prefix}[ProductName]"),
+            appt.product_name.clone(),
+        );
+
+        for (k, v) in default_fields() {
+            form.insert(format!("{prefix}[{k}]"), v.to_string());
+        }
+        // Whatever the calendar gave beats the placeholders
+        for (k, v) in appt.known_fields() {
+            form.insert(format!("{prefix}[{k}]"), v);
+        }
+    }
+
+    form.insert("programID".into(), program_id.into());
+    form.insert("year".into(), date.year().to_string());
+    form.insert("month".into(), date.month().to_string());
+    form.insert("day".into(), date.day().to_string());
+
+    // POST and parse every appointment's card out of the one response
+    let res = with_retry(&config.retry, &format!("Fetching sessions on {date} for program {program_id}"), || async {
+        rate_limit::acquire(&config.rate_limit).await;
+        let response = client
+            .post(format!("{}{FILTER_PATH}", config.site_url.trim_end_matches('/')))
+            .form(&form)
+            .send()
+            .await?;
+        let response = check_response(response, &config.rate_limit)?;
+        read_body(response, config.max_response_bytes).await
+    })
+    .await?;
+    check_cards_match(program_id, appts, &res)?;
+    Ok(appts.iter().map(|appt| parse_instance(&res, &appt.id)).collect())
+}
+
+/// Make sure the cards the site answered with belong to the appointments asked about,
+/// rather than to some other program's sessions
+fn check_cards_match(program_id: &str, appts: &[&Appointment], html: &str) -> Result<()> {
+    let cards = instance_appointment_ids(html);
+    if cards.is_empty() || appts.iter().any(|appt| cards.contains(&appt.id)) {
+        return Ok(());
+    }
+    let asked: Vec<&str> = appts.iter().map(|appt| appt.id.as_str()).collect();
+    Err(Error::Parse(format!(
+        "sessions fetched for program {} came back as appointments {} instead of {}",
+        program_id,
+        cards.join(", "),
+        asked.join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cards_for_other_appointments_are_a_mismatch() {
+        let appt = Appointment { id: "evening".into(), ..Default::default() };
+        let card = |id: &str| format!("<div data-instance-appointmentid='{id}'><span class='spots-tag'>Full</span></div>");
+
+        assert!(check_cards_match("tennis", &[&appt], &card("evening")).is_ok());
+        // No cards at all is just an empty day, not somebody else's
+        assert!(check_cards_match("tennis", &[&appt], "<html></html>").is_ok());
+
+        let error = check_cards_match("tennis", &[&appt], &(card("squash-1") + &card("squash-2"))).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error: sessions fetched for program tennis came back as appointments squash-1, squash-2 instead of evening"
+        );
+    }
+
+    #[test]
+    fn retry_after_is_seconds_or_a_date() {
+        assert_eq!(parse_retry_after(" 120"), Some(Duration::from_secs(120)));
+        // A date already past means right away
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn browser_fallback_is_only_for_unreadable_pages() {
+        // Answers with the status the site URL starts with, and a page that isn't a calendar
+        let app = axum::Router::new().fallback(|uri: axum::http::Uri| async move {
+            let status = uri.path().split('/').nth(1).unwrap().parse().unwrap();
+            (axum::http::StatusCode::from_u16(status).unwrap(), [("content-type", "text/html")], "<html></html>")
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let fetch = |status: u16| async move {
+            let config: Config = toml::from_str(&format!(r#"
+                site_url = "http://{address}/{status}"
+                browser_fallback = true
+                rate_limit = {{ requests_per_second = 0 }}
+                retry = {{ max_attempts = 1 }}
+                program_ids = []
+            "#)).unwrap();
+            fetch_initial(&build_client().unwrap(), &config, "tennis").await.unwrap_err()
+        };
+
+        assert!(matches!(fetch(401).await, Error::Auth(_)));
+        assert!(matches!(fetch(429).await, Error::RateLimited { .. }));
+        if cfg!(not(feature = "browser")) {
+            // An unreadable page goes to the browser, which this build doesn't have
+            assert!(matches!(fetch(200).await, Error::Config(_)));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Friends' weekly availability, used to point out who could join an open session
+
+use chrono::{Datelike, NaiveTime};
+use serde::Deserialize;
+use std::fs;
+
+use crate::config::{Config, FriendConfig};
+use crate::error::Result;
+use crate::parse::SpotInfo;
+
+// Layout of a `friends_path` file, a list of `[[friends]]` tables
+#[derive(Debug, Deserialize)]
+struct FriendsFile {
+    #[serde(default)]
+    friends: Vec<FriendConfig>,
+}
+
+/// Friends from the config followed by those imported from `friends_path`
+pub fn load(config: &Config) -> Result<Vec<FriendConfig>> {
+    let mut friends = config.friends.clone();
+    if let Some(path) = &config.friends_path {
+        let file: FriendsFile = toml::from_str(&fs::read_to_string(path)?)?;
+        friends.extend(file.friends);
+    }
+    Ok(friends)
+}
+
+impl FriendConfig {
+    /// Whether one of this friend's weekly slots covers the whole session
+    pub fn is_free_for(&self, spot: &SpotInfo) -> bool {
+        let Some(start) = spot.start() else {
+            return false;
+        };
+        let end = NaiveTime::parse_from_str(&spot.end_time, "%H:%M:%S").unwrap_or(start.time());
+
+        self.free.iter().any(|slot| {
+            (slot.days.is_empty() || slot.days.contains(&start.weekday()))
+                && slot.hours.contains(start.time())
+                && (slot.hours.contains(end) || end == slot.hours.end)
+        })
+    }
+}
+
+/// Names of the friends who are free for the whole session
+pub fn free_friends<'a>(friends: &'a [FriendConfig], spot: &SpotInfo) -> Vec<&'a str> {
+    friends
+        .iter()
+        .filter(|friend| friend.is_free_for(spot))
+        .map(|friend| friend.name.as_str())
+        .collect()
+}
+
+/// A line like "Alex and Sam are free then" for an open-spot alert, or nothing
+pub fn friends_note(friends: &[FriendConfig], spot: &SpotInfo) -> String {
+    let names = free_friends(friends, spot);
+    let listed = match names.as_slice() {
+        [] => return String::new(),
+        [name] => return format!("\n👥 {name} is free then"),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    };
+    format!("\n👥 {listed} are free then")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn friends_free_for_the_whole_session_are_noted() {
+        let config: Config = toml::from_str(r#"
+            interval_seconds = 60
+            program_ids = []
+
+            [[friends]]
+            name = "Alex"
+            free = [{ days = ["fri"], hours = "18:00-22:00" }]
+
+            [[friends]]
+            name = "Sam"
+            free = [{ hours = "19:00-20:30" }]
+
+            [[friends]]
+            name = "Jo"
+            free = [{ days = ["Friday"], hours = "17:00-20:00" }, { days = ["sat"], hours = "18:00-23:00" }]
+        "#).unwrap();
+        let session = spot("Beginner", "2 spots left");
+
+        assert_eq!(friends_note(&config.friends, &session), "\n👥 Alex and Sam are free then");
+        assert_eq!(friends_note(&config.friends[2..], &session), "");
+    }
+}
@@ -0,0 +1,213 @@
+//! Personal record of the sessions actually attended
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::parse::{SpotInfo, price_amount};
+use crate::state::Sessions;
+
+// One attended session, stored as a line of JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub program_name: String,
+    pub product_name: String,
+    pub location: String,
+    pub date: String,
+    pub time: String,
+    // What the session cost, as scraped ("$5.00", "Free")
+    #[serde(default)]
+    pub price: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl JournalEntry {
+    pub fn new(spot: &SpotInfo) -> Self {
+        JournalEntry {
+            program_name: spot.program_name.to_string(),
+            product_name: spot.product_name.to_string(),
+            location: spot.location.to_string(),
+            date: spot.date.to_string(),
+            time: spot.time.to_string(),
+            price: spot.price.clone(),
+            recorded_at: Utc::now(),
+        }
+    }
+
+    /// "2025-03", the month the session took place in
+    pub fn month(&self) -> &str {
+        self.date.get(..7).unwrap_or(&self.date)
+    }
+}
+
+/// Every entry in the journal at `path`; a missing file is an empty journal
+pub fn load(path: &Path) -> Result<Vec<JournalEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Add an entry to the journal at `path`
+pub fn record(path: &Path, entry: &JournalEntry) -> Result<()> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Sessions attended per month, broken down by program
+pub fn monthly_totals(entries: &[JournalEntry]) -> BTreeMap<String, BTreeMap<String, usize>> {
+    let mut totals: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+    for entry in entries {
+        *totals
+            .entry(entry.month().to_string())
+            .or_default()
+            .entry(entry.program_name.clone())
+            .or_default() += 1;
+    }
+    totals
+}
+
+// Money spent on one program in one month
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spend {
+    // Attended sessions that had a price
+    pub sessions: usize,
+    pub total: f64,
+}
+
+/// Spending per month, broken down by program; sessions without a known price are left out
+pub fn monthly_spend(entries: &[JournalEntry]) -> BTreeMap<String, BTreeMap<String, Spend>> {
+    let mut spend: BTreeMap<String, BTreeMap<String, Spend>> = BTreeMap::new();
+    for entry in entries {
+        let Some(amount) = entry.price.as_deref().and_then(price_amount) else {
+            continue;
+        };
+        let program = spend
+            .entry(entry.month().to_string())
+            .or_default()
+            .entry(entry.program_name.clone())
+            .or_default();
+        program.sessions += 1;
+        program.total += amount;
+    }
+    spend
+}
+
+/// Monthly spend as CSV with a header row
+pub fn spend_csv(spend: &BTreeMap<String, BTreeMap<String, Spend>>) -> String {
+    let mut csv = String::from("month,program,sessions,total\n");
+    for (month, programs) in spend {
+        for (program, spend) in programs {
+            csv.push_str(&format!("{},{},{},{:.2}\n", month, csv_field(program), spend.sessions, spend.total));
+        }
+    }
+    csv
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Find the session the user means by date, start time ("19:00") and an optional
+/// program or session name, among tracked sessions and the session history
+pub fn find_session(config: &Config, date: &str, time: &str, name: Option<&str>) -> Result<SpotInfo> {
+    let mut candidates: Vec<SpotInfo> = Vec::new();
+    if let Some(path) = &config.state_path {
+        candidates.extend(Sessions::load(Path::new(path))?.spots.into_values());
+    }
+    if let Some(path) = &config.history_path
+        && let Ok(text) = fs::read_to_string(path)
+    {
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            candidates.push(serde_json::from_str(line)?);
+        }
+    }
+
+    let name = name.map(str::to_lowercase);
+    let mut matches: Vec<SpotInfo> = candidates
+        .into_iter()
+        .filter(|spot| *spot.date == *date && spot.time.starts_with(time))
+        .filter(|spot| {
+            name.as_ref().is_none_or(|name| {
+                spot.program_name.to_lowercase().contains(name) || spot.product_name.to_lowercase().contains(name)
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| a.identity.cmp(&b.identity));
+    matches.dedup_by(|a, b| a.identity == b.identity);
+
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => Err(Error::Config(format!("no tracked session on {} at {}", date, time))),
+        _ => Err(Error::Config(format!(
+            "{} sessions on {} at {} ({}), add a program name to pick one",
+            matches.len(),
+            date,
+            time,
+            matches.iter().map(|spot| &*spot.program_name).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn journal_totals_by_month_and_program() {
+        let entry = |program: &str, date: &str| {
+            let mut attended = spot(program, "Full");
+            attended.date = date.into();
+            JournalEntry::new(&attended)
+        };
+        let entries = [
+            entry("Beginner", "2025-03-14"),
+            entry("Beginner", "2025-03-21"),
+            entry("Advanced", "2025-03-28"),
+            entry("Beginner", "2025-04-04"),
+        ];
+
+        let totals = monthly_totals(&entries);
+        assert_eq!(totals["2025-03"]["Beginner"], 2);
+        assert_eq!(totals["2025-03"]["Advanced"], 1);
+        assert_eq!(totals["2025-04"].values().sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn spending_sums_priced_sessions_as_csv() {
+        let entry = |program: &str, price: Option<&str>| {
+            let mut attended = spot(program, "Full");
+            attended.price = price.map(Into::into);
+            JournalEntry::new(&attended)
+        };
+        let entries = [
+            entry("Beginner", Some("$5.00")),
+            entry("Beginner", Some("$7.50")),
+            entry("Beginner", None),
+            entry("Drop-in, Advanced", Some("Free")),
+        ];
+
+        let spend = monthly_spend(&entries);
+        assert_eq!(
+            spend_csv(&spend),
+            "month,program,sessions,total\n2025-03,Beginner,2,12.50\n2025-03,\"Drop-in, Advanced\",1,0.00\n"
+        );
+    }
+}
@@ -0,0 +1,818 @@
+//! Watch UW Warrior tennis programs for open spots and notify about changes.
+//!
+//! [`run`] is the whole monitor; the modules expose its pieces for embedding
+//! the fetching, parsing or notifying elsewhere.
+
+/// `println!` that strips emoji and symbols in plain-text mode
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::style::print(&format!($($arg)*))
+    };
+}
+
+/// `say!` for lines only printed in verbose mode
+#[macro_export]
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        $crate::style::print_detail(&format!($($arg)*))
+    };
+}
+
+#[cfg(feature = "browser")]
+mod browser;
+pub mod clock;
+pub mod config;
+pub mod cron;
+pub mod debug;
+#[cfg(feature = "email")]
+mod email;
+pub mod error;
+pub mod facility;
+pub mod federation;
+pub mod fetch;
+pub mod friends;
+pub mod journal;
+pub mod mirror;
+pub mod notify;
+pub mod parse;
+pub mod rate_limit;
+pub mod reload;
+pub mod retry;
+pub mod shutdown;
+pub mod state;
+pub mod style;
+pub mod sun;
+pub mod telegram;
+pub mod travel;
+pub mod vote;
+
+use chrono::{DateTime, NaiveTime};
+use chrono_tz::Tz;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::{Semaphore, mpsc, watch};
+
+use clock::{Clock, Schedule, SystemClock};
+use config::{ComparisonConfig, FirstSeen, NotifierConfig, NotifierEntry, ProgramConfig, UserConfig};
+use facility::map_note;
+use fetch::{build_client, capture_calendar, check_program, save_screenshot};
+use friends::friends_note;
+use federation::Peers;
+use mirror::{GitHistory, Mirror, Snapshot};
+use notify::{
+    Digest, Notification, Priority, format_change, membership_note, notify_all, notify_or_hold,
+    render_template, sync_caldav,
+};
+use parse::{classify_spots, is_free_price, prune_interned, unknown_badges};
+use state::{HealthState, MonitorStatus, ProgramHealth, Sessions, append_history, merge_duplicates, spots_changed};
+use vote::WeeklyVote;
+
+pub use config::Config;
+pub use error::{Error, Result};
+pub use parse::{Appointment, SpotInfo};
+
+/// Check every configured program on its schedule, notifying about changes, until
+/// Ctrl-C or SIGTERM
+pub async fn run(config: Arc<Config>) -> Result<()> {
+    run_until(config, shutdown::listen(), mpsc::channel(1).1).await
+}
+
+/// Like `run`, picking up changes to the config file at `path` as they're saved or
+/// on SIGHUP
+pub async fn run_reloading(config: Arc<Config>, path: PathBuf) -> Result<()> {
+    run_until(config, shutdown::listen(), reload::watch(path)).await
+}
+
+/// Like `run`, stopping once `stopping` turns true (the cycle in flight finishes and
+/// the state is saved first) and switching to each config that comes in on `reloads`
+pub async fn run_until(config: Arc<Config>, stopping: watch::Receiver<bool>, reloads: mpsc::Receiver<Config>) -> Result<()> {
+    monitor(config, stopping, reloads, false).await
+}
+
+/// Check every program once, cron-scheduled ones included, save the state and return;
+/// for running from cron or a systemd timer instead of as a service
+pub async fn run_once(config: Arc<Config>) -> Result<()> {
+    monitor(config, shutdown::listen(), mpsc::channel(1).1, true).await
+}
+
+/// Check every program once and return what's on offer, without tracking, saving or
+/// notifying anything; a program that fails to load is reported and left out
+pub async fn check_all(config: Arc<Config>) -> Result<Snapshot> {
+    parse::set_spot_patterns(config.spot_patterns.iter().map(|pattern| pattern.0.clone()).collect());
+    let client = build_client()?;
+    let check_slots = check_slots(&config);
+    let tasks: Vec<_> = config
+        .program_ids
+        .iter()
+        .map(|program| {
+            let (client, config, program, check_slots) = (client.clone(), config.clone(), program.clone(), check_slots.clone());
+            tokio::spawn(async move {
+                let _slot = check_slots.acquire_owned().await;
+                let result = check_program(&client, &config, &program).await;
+                if let Err(e) = &result {
+                    eprintln!("Error checking program {} ({} error): {}", program.name, e.kind(), e);
+                }
+                result
+            })
+        })
+        .collect();
+
+    let mut spots = HashMap::new();
+    for task in tasks {
+        if let Ok(Ok(program_spots)) = task.await {
+            spots.extend(program_spots);
+        }
+    }
+    Ok(Snapshot::new(&spots, SystemClock.now().naive_local()))
+}
+
+async fn monitor(
+    mut config: Arc<Config>,
+    mut stopping: watch::Receiver<bool>,
+    mut reloads: mpsc::Receiver<Config>,
+    once: bool,
+) -> Result<()> {
+    let mut notifiers = apply_config(&config);
+    let mut program_notifiers = program_routes(&config);
+    let mut friends = load_friends(&config);
+
+    let client = build_client()?;
+    // Bounds how many program checks run at once
+    let mut check_slots = check_slots(&config);
+
+    // Track previous spots to detect changes, picking up where the last run left off
+    let mut sessions = match &config.state_path {
+        Some(path) => match Sessions::load(Path::new(path)) {
+            Ok(sessions) => {
+                say!("💾 Loaded {} tracked sessions from {}", sessions.spots.len(), path);
+                sessions
+            }
+            Err(e) => {
+                eprintln!("Failed to load state from {}, starting fresh: {}", path, e);
+                Sessions::default()
+            }
+        },
+        None => Sessions::default(),
+    };
+    // What changed while we weren't running goes out as one digest on the first check,
+    // rather than a notification per session (a single run just carries on as usual)
+    let mut catching_up = !once && !sessions.spots.is_empty();
+    // Shared with the Telegram command handlers
+    let status = Arc::new(MonitorStatus::default());
+    status.publish(&sessions.spots);
+    for entry in &notifiers {
+        if let NotifierConfig::Telegram(telegram) = &entry.notifier
+            && telegram.commands
+            && config.enable_server
+        {
+            tokio::spawn(telegram::poll_commands(telegram.clone(), status.clone()));
+        }
+    }
+
+    // Per-program check health, by program ID
+    let mut health: HashMap<Arc<str>, ProgramHealth> = HashMap::new();
+    // CalDAV events currently on the server, by event UID, with their summary
+    let mut published_events: HashMap<String, String> = HashMap::new();
+    let mut vote = config.voting.clone().map(WeeklyVote::new);
+    let mut mirror = config.mirror.clone().map(Mirror::new);
+    let mut history = config.git_history.clone().map(GitHistory::new);
+    let mut peers = Peers::new(config.peers.clone());
+    // Session notifications held through quiet hours
+    let mut overnight = Digest::overnight();
+    // Sessions flagged stale after the last cycle
+    let mut stale_count = 0;
+    
+    // Main loop for periodic checking
+    let clock = SystemClock;
+    let mut schedule = Schedule::new(Duration::from_secs(config.interval_seconds)).with_bursts(config.burst_windows.clone());
+    let mut interval = schedule.interval_at(clock.now());
+    // When the next interval tick is due, and when each cron-scheduled program is next due
+    let mut tick_at = None;
+    let mut cron_due = cron_schedule(&config, clock.now());
+    let mut cycle: u64 = 0;
+    loop {
+        // Wake for whichever comes first, the next interval tick or a cron program
+        let tick = *tick_at.get_or_insert_with(|| clock.now() + schedule.next_delay(clock.now()));
+        let wake = cron_due.values().copied().fold(tick, DateTime::min);
+        detail!("😴 Next check at {}", wake.format("%H:%M:%S"));
+        tokio::select! {
+            _ = clock.sleep((wake - clock.now()).to_std().unwrap_or_default()) => {}
+            // A dropped sender never asks to stop
+            Ok(_) = stopping.wait_for(|stop| *stop) => break,
+            Some(reloaded) = reloads.recv() => {
+                let reloaded = Arc::new(reloaded);
+                say!("🔁 Reloaded the config; voting, Telegram commands and [runtime] still need a restart");
+                // Sessions of programs no longer listed go; everyone else's history stays
+                let ids: HashSet<&str> = reloaded.program_ids.iter().map(|program| &*program.id).collect();
+                for program in config.program_ids.iter().filter(|program| !ids.contains(&*program.id)) {
+                    say!("➖ Stopped tracking {}", program.label());
+                }
+                let known: HashSet<&str> = config.program_ids.iter().map(|program| &*program.id).collect();
+                for program in reloaded.program_ids.iter().filter(|program| !known.contains(&*program.id)) {
+                    say!("➕ Now tracking {}", program.label());
+                }
+                let dropped = sessions.retain_programs(|program_id| ids.contains(program_id));
+                if dropped > 0 {
+                    say!("🗑️ Dropped {} sessions of removed programs", dropped);
+                }
+                health.retain(|program_id, _| ids.contains(&**program_id));
+
+                config = reloaded;
+                notifiers = apply_config(&config);
+                program_notifiers = program_routes(&config);
+                friends = load_friends(&config);
+                check_slots = self::check_slots(&config);
+                mirror = config.mirror.clone().map(Mirror::new);
+                history = config.git_history.clone().map(GitHistory::new);
+                peers = Peers::new(config.peers.clone());
+                // Check everything right away under the new settings
+                schedule = Schedule::new(Duration::from_secs(config.interval_seconds)).with_bursts(config.burst_windows.clone());
+                interval = schedule.interval_at(clock.now());
+                tick_at = None;
+                cron_due = cron_schedule(&config, clock.now());
+                status.publish(&sessions.spots);
+                *status.config_changes.lock().unwrap_or_else(|e| e.into_inner()) = reload::audit_trail();
+                continue;
+            }
+        }
+        let notifiers_for = |program_ids: &[&Arc<str>]| routes_for(&program_notifiers, &notifiers, program_ids);
+        let now = clock.now();
+        let ticked = tick <= now;
+        if ticked {
+            tick_at = None;
+        }
+        let due: Vec<&ProgramConfig> = config
+            .program_ids
+            .iter()
+            .filter(|program| match &program.cron {
+                None => ticked,
+                Some(_) => once || cron_due.get(&program.id).is_some_and(|due| *due <= now),
+            })
+            .collect();
+        for program in &due {
+            if let Some(cron) = &program.cron {
+                // Past this minute, so a quick check doesn't run twice in it
+                match cron.next_from(now + chrono::Duration::minutes(1)) {
+                    Some(next) => cron_due.insert(program.id.clone(), next),
+                    None => cron_due.remove(&program.id),
+                };
+            }
+        }
+
+        let now_interval = schedule.interval_at(now);
+        if ticked && now_interval != interval {
+            interval = now_interval;
+            say!("⚡ Now checking every {} seconds", interval.as_secs());
+        }
+        if status.paused.load(Ordering::Relaxed) {
+            say!("⏸️ Paused, skipping this check");
+            continue;
+        }
+        // A tick with only cron-scheduled programs still keeps everything else going
+        if !due.is_empty() {
+            say!("⏱️ Checking for spot changes...");
+        }
+
+        // Nothing is sent during the first few cycles so a fresh deployment doesn't flood anyone;
+        // a single run picks up from the saved state instead
+        cycle += 1;
+        let warming_up = !once && cycle <= config.warmup_cycles;
+        if warming_up {
+            say!("🤫 Warming up ({}/{}), notifications are muted", cycle, config.warmup_cycles);
+        }
+        let muted = warming_up || !config.enable_notifications;
+        let quiet = config.quiet_hours.is_some_and(|window| window.contains(now.time()));
+
+        // Quiet hours are over: send what changed during them, or let it go
+        if !quiet && !overnight.is_empty() {
+            if config.quiet_hours_digest {
+                say!("🌅 Quiet hours are over, sending {} held notifications", overnight.len());
+                overnight.flush().await;
+            } else {
+                overnight.clear();
+            }
+        }
+        
+        // Process each program ID concurrently
+        let mut tasks = Vec::new();
+        for program in due {
+            let client = client.clone();
+            let config = config.clone();
+            let program = program.clone();
+            let check_slots = check_slots.clone();
+            
+            let task = tokio::spawn(async move {
+                let _slot = check_slots.acquire_owned().await;
+                let result = check_program(&client, &config, &program).await;
+                if let Err(e) = &result {
+                    eprintln!("Error checking program {} ({} error): {}", program.name, e.kind(), e);
+                    if let Some(dir) = &config.screenshot_dir
+                        && matches!(e, Error::Parse(_))
+                    {
+                        save_screenshot(dir, &program.id).await;
+                    }
+                }
+                (program, result)
+            });
+            
+            tasks.push(task);
+        }
+        
+        // Wait for all tasks to complete and collect their results
+        let mut cycle_spots = Vec::new();
+        let now = clock.now();
+        for task in tasks {
+            let Ok((program, result)) = task.await else {
+                continue;
+            };
+
+            let program_health = health
+                .entry(program.id.clone())
+                .or_insert_with(|| ProgramHealth::new(now));
+            let previous_state = program_health.state;
+            program_health.record(result.as_ref().map(|_| ()), now);
+
+            // Recoveries always notify, repeated identical errors back off
+            let worth_notifying = match &result {
+                Ok(_) => program_health.state != previous_state,
+                Err(_) => program_health.should_notify_error(),
+            };
+            if worth_notifying && !muted && !quiet && config.notify_health {
+                // The program's sessions stop being refreshed while it fails
+                let stale = sessions
+                    .spots
+                    .values()
+                    .filter(|spot| spot.program_ids().any(|id| *id == program.id) && spot.is_stale(now.naive_local(), config.stale_after()))
+                    .count();
+                let mut message = program_health.describe(&program.name);
+                if result.is_err() && stale > 0 {
+                    message.push_str(&format!("\nSpot counts for its {} sessions are out of date", stale));
+                }
+                notify_all(&notifiers_for(&[&program.id]), &Notification::new(
+                    format!("{} is {}", program.name, program_health.state),
+                    message,
+                )).await;
+            }
+
+            if let Ok(current_spots) = result {
+                detail!("🔎 {}: {} sessions", program.label(), current_spots.len());
+                cycle_spots.extend(current_spots);
+            }
+        }
+
+        // Only worth a line when something isn't healthy
+        if health.values().any(|program_health| program_health.state != HealthState::Ok) {
+            for program in &config.program_ids {
+                if let Some(program_health) = health.get(&program.id) {
+                    say!("🩺 {}", program_health.describe(&program.label()));
+                }
+            }
+        }
+
+        // Process each session once, even if several programs list it
+        let mut listed = HashSet::new();
+        let mut cycle_digest = if catching_up && ticked { Digest::while_away() } else { Digest::default() };
+        let held_overnight = overnight.len();
+        // Through quiet hours everything is held for the morning instead
+        let digest = if quiet { &mut overnight } else { &mut cycle_digest };
+        for (key, spot_info) in merge_duplicates(cycle_spots) {
+            listed.insert(key.clone());
+            // Fall back to the session identity if the appointment ID changed
+            let prev_info = sessions.take_previous(&key, &spot_info).map(|(prev, rekeyed)| {
+                if rekeyed {
+                    say!("🔁 Session re-keyed: {} ({}) on {} @ {}",
+                        config.program_label(&spot_info), spot_info.product_name,
+                        spot_info.date, spot_info.time);
+                }
+                prev
+            });
+
+            // Alerts about sessions I couldn't get to in time, or couldn't see the ball at, are pointless
+            let facility = config.facility(&spot_info.location);
+            let reachable = config
+                .travel
+                .as_ref()
+                .is_none_or(|travel| travel.reachable(&spot_info, facility, clock.now().naive_local()));
+            let after_dark = config
+                .sunset_guard
+                .as_ref()
+                .is_some_and(|guard| guard.ends_after_sunset(&spot_info, facility));
+            let playable = reachable && !after_dark;
+            let travel_note = config.travel.as_ref().map(|travel| travel.note(&spot_info, facility)).unwrap_or_default();
+
+            // Check if spots have changed
+            if let Some(prev_info) = prev_info {
+                if spots_changed(&prev_info.spots, &spot_info.spots) {
+                    // Every program listing the session decides for itself
+                    let notifying_on: Vec<&Arc<str>> = spot_info
+                        .program_ids()
+                        .filter(|id| config.notify_on(id).allows(&prev_info.spots, &spot_info.spots))
+                        .collect();
+                    let meeting_min_spots: Vec<&Arc<str>> =
+                        notifying_on.iter().copied().filter(|id| config.meets_min_spots(id, &spot_info)).collect();
+                    say!("🔄 Change detected: {} ({}) on {} @ {} - {} → {}", 
+                        config.program_label(&spot_info), spot_info.product_name, 
+                        spot_info.date, spot_info.time, 
+                        prev_info.spots, spot_info.spots);
+                    
+                    let owner_wants = if !reachable {
+                        say!("🕒 Too late to get there, not notifying");
+                        false
+                    } else if after_dark {
+                        say!("🌇 Outdoor session ends after sunset, not notifying");
+                        false
+                    } else if notifying_on.is_empty() {
+                        say!("🔕 Not the kind of change this program notifies on");
+                        false
+                    } else if meeting_min_spots.is_empty() {
+                        say!("🔕 Fewer open spots than this program's min_spots, not notifying");
+                        false
+                    } else {
+                        true
+                    };
+                    // Every user decides for themselves, whatever the filters above said. Users
+                    // can't be held for the morning digest, so they stay silent through quiet hours
+                    let users = users_wanting(&config, &spot_info, quiet, clock.now().time());
+
+                    if !muted && (owner_wants || !users.is_empty()) {
+                        // Send notification
+                        let title = format!("Spot change: {}", spot_info.product_name);
+                        let mut message = format_change(&prev_info, &spot_info, config.ntfy_markdown);
+                        if classify_spots(&spot_info.spots).is_open() {
+                            message.push_str(&friends_note(&friends, &spot_info));
+                            message.push_str(&travel_note);
+                        }
+                        let mut notification = Notification {
+                            tags: config.program_tags(&spot_info),
+                            markdown: config.ntfy_markdown,
+                            spot: Some(spot_info.clone()),
+                            previous_spots: Some(prev_info.spots.clone()),
+                            priority: Some(Priority::for_change(&prev_info.spots, &spot_info.spots)),
+                            ..Notification::new(&title, format!("{}{}", message, map_note(config.map_links, &spot_info, facility)))
+                        };
+                        if config.attach_screenshots {
+                            notification.attachment = capture_calendar(&spot_info.program_id)
+                                .await
+                                .map_err(|e| eprintln!("Failed to capture screenshot: {}", e))
+                                .ok();
+                        }
+                        if owner_wants {
+                            notify_or_hold(&notifiers_for(&meeting_min_spots), &notification, digest).await;
+                        }
+                        // Users never reach the overnight digest, but get their own after a restart
+                        for user in users {
+                            notify_or_hold(&[user.notifier()], &Notification {
+                                message: format!("{}{}", message, map_note(user.map_links, &spot_info, facility)),
+                                ..notification.clone()
+                            }, digest).await;
+                        }
+
+                        // Announce the session opening to the public channel, once rather than
+                        // on every count change while it stays open; like users, it can't wait
+                        // for the morning
+                        if owner_wants
+                            && !quiet
+                            && let Some(broadcast) = &config.broadcast
+                            && !classify_spots(&prev_info.spots).is_open()
+                            && classify_spots(&spot_info.spots).is_open()
+                        {
+                            notify_all(&[broadcast.notifier()], &Notification::new(
+                                render_template(&broadcast.title, &spot_info),
+                                render_template(&broadcast.template, &spot_info),
+                            )).await;
+                        }
+                    }
+                }
+
+                // Check if the price has changed
+                if !muted && config.notify_price_changes && prev_info.price != spot_info.price {
+                    let old_price = prev_info.price.as_deref().unwrap_or("no price");
+                    let new_price = spot_info.price.as_deref().unwrap_or("no price");
+                    say!("💲 Price change: {} ({}) on {} @ {} - {} → {}",
+                        config.program_label(&spot_info), spot_info.product_name,
+                        spot_info.date, spot_info.time, old_price, new_price);
+
+                    notify_or_hold(&notifiers_for(&spot_info.program_ids().collect::<Vec<_>>()), &Notification {
+                        tags: config.program_tags(&spot_info),
+                        spot: Some(spot_info.clone()),
+                        ..Notification::new(
+                            format!("Price change: {}", spot_info.product_name),
+                            format!("{} ({}) on {} @ {}: {} → {}",
+                                spot_info.programs_label(), spot_info.product_name,
+                                spot_info.date, spot_info.time, old_price, new_price),
+                        )
+                    }, digest).await;
+                }
+            } else {
+                // First time seeing this appointment
+                if config.on_first_seen != FirstSeen::Silent {
+                    say!("📌 New tracking: {} ({}) on {} @ {} - {}", 
+                        config.program_label(&spot_info), spot_info.product_name, 
+                        spot_info.date, spot_info.time, spot_info.spots);
+                }
+
+                let meeting_min_spots: Vec<&Arc<str>> =
+                    spot_info.program_ids().filter(|id| config.meets_min_spots(id, &spot_info)).collect();
+                if !muted
+                    && config.on_first_seen == FirstSeen::NotifyIfAvailable
+                    && classify_spots(&spot_info.spots).is_open()
+                {
+                    let owner_wants = playable && !meeting_min_spots.is_empty();
+                    let users = users_wanting(&config, &spot_info, quiet, clock.now().time());
+                    let message = format!("{} ({}) on {} @ {}: {}{}{}{}",
+                        spot_info.programs_label(), spot_info.product_name,
+                        spot_info.date, spot_info.time, spot_info.spots,
+                        membership_note(&spot_info), friends_note(&friends, &spot_info), travel_note);
+                    let notification = Notification {
+                        tags: config.program_tags(&spot_info),
+                        spot: Some(spot_info.clone()),
+                        priority: Some(Priority::High),
+                        ..Notification::new(
+                            format!("Spot available: {}", spot_info.product_name),
+                            format!("{}{}", message, map_note(config.map_links, &spot_info, facility)),
+                        )
+                    };
+                    if owner_wants {
+                        notify_or_hold(&notifiers_for(&meeting_min_spots), &notification, digest).await;
+                    }
+                    for user in users {
+                        notify_or_hold(&[user.notifier()], &Notification {
+                            message: format!("{}{}", message, map_note(user.map_links, &spot_info, facility)),
+                            ..notification.clone()
+                        }, digest).await;
+                    }
+                }
+
+                if !muted
+                    && playable
+                    && config.notify_free_drop_ins
+                    && spot_info.price.as_deref().is_some_and(is_free_price)
+                {
+                    notify_or_hold(&notifiers_for(&spot_info.program_ids().collect::<Vec<_>>()), &Notification {
+                        tags: config.program_tags(&spot_info),
+                        spot: Some(spot_info.clone()),
+                        ..Notification::new(
+                            format!("Free drop-in: {}", spot_info.product_name),
+                            format!("{} ({}) on {} @ {}: {}{}{}{}{}",
+                                spot_info.programs_label(), spot_info.product_name,
+                                spot_info.date, spot_info.time, spot_info.spots,
+                                membership_note(&spot_info), friends_note(&friends, &spot_info), travel_note,
+                                map_note(config.map_links, &spot_info, facility)),
+                        )
+                    }, digest).await;
+                }
+            }
+            
+            // Update previous spots
+            sessions.insert(key, spot_info);
+        }
+
+        // Peers' changes follow the same rules as ours for muting and quiet hours
+        if ticked {
+            for notification in peers.poll(&client, &sessions.spots).await {
+                say!("🤝 {}", notification.title);
+                if !muted {
+                    notify_or_hold(&notifiers, &notification, digest).await;
+                }
+            }
+        }
+
+        if quiet && digest.len() != held_overnight {
+            say!("🌙 Quiet hours, holding {} notifications for later", digest.len());
+        }
+        if catching_up && ticked {
+            catching_up = false;
+            if !cycle_digest.is_empty() {
+                say!("📬 Sending a digest of {} changes made while the monitor was down", cycle_digest.len());
+            }
+        }
+        cycle_digest.flush().await;
+
+        // Close out sessions whose start time has come
+        let passed = sessions.close_passed(clock.now().naive_local(), &listed);
+        for session in &passed {
+            let spot = &session.spot;
+            say!("🏁 Session {}: {} ({}) on {} @ {} - {}",
+                session.lifecycle, config.program_label(spot), spot.product_name,
+                spot.date, spot.time, spot.spots);
+        }
+        if let Some(path) = &config.history_path
+            && !passed.is_empty()
+            && let Err(e) = append_history(Path::new(path), &passed)
+        {
+            eprintln!("Failed to write history to {}: {}", path, e);
+        }
+        if !passed.is_empty() {
+            // Closed sessions may have been the last to use some of the pooled strings
+            drop(passed);
+            prune_interned();
+        }
+
+        // Sessions of programs that keep failing hold on to old numbers; say so rather than pass them off as current
+        let stale = sessions.mark_stale(clock.now().naive_local(), config.stale_after());
+        if stale != stale_count {
+            if stale > 0 {
+                say!("🕰️ {} sessions haven't been refreshed in over {} minutes, flagged stale",
+                    stale, config.stale_after().num_minutes());
+            }
+            stale_count = stale;
+        }
+        status.publish(&sessions.spots);
+        status.unknown_badges.store(unknown_badges(), Ordering::Relaxed);
+        if mirror.is_some() || history.is_some() {
+            let snapshot = Snapshot::new(&sessions.spots, clock.now().naive_local());
+            if let Some(mirror) = &mut mirror
+                && let Err(e) = mirror.publish(&snapshot).await
+            {
+                eprintln!("Failed to update the public mirror: {}", e);
+            }
+            if let Some(history) = &mut history
+                && let Err(e) = history.record(&snapshot).await
+            {
+                eprintln!("Failed to commit availability history: {}", e);
+            }
+        }
+
+        if let Some(vote) = &mut vote
+            && config.enable_notifications
+        {
+            vote.tick(clock.now(), &sessions.spots, &notifiers).await;
+        }
+
+        if let Some(path) = &config.state_path
+            && let Err(e) = sessions.save(Path::new(path))
+        {
+            eprintln!("Failed to save state to {}: {}", path, e);
+        }
+
+        for comparison in &config.comparisons {
+            print_comparison(comparison, &sessions.spots);
+        }
+
+        // Mirror currently open sessions to the CalDAV calendar
+        if let Some(caldav) = &config.caldav {
+            sync_caldav(&client, caldav, &mut published_events, &sessions.spots).await;
+        }
+
+        if once {
+            break;
+        }
+    }
+
+    // Stopping: leave everything where the next run can pick it up
+    if let Some(path) = &config.state_path {
+        match sessions.save(Path::new(path)) {
+            Ok(()) => say!("💾 Saved {} tracked sessions to {}", sessions.spots.len(), path),
+            Err(e) => eprintln!("Failed to save state to {}: {}", path, e),
+        }
+    }
+    if !overnight.is_empty() {
+        say!("🌙 Dropping {} notifications held for the end of quiet hours", overnight.len());
+    }
+    if config.notify_on_shutdown && config.enable_notifications && !once {
+        notify_all(&notifiers, &Notification::new(
+            "Monitor stopping",
+            format!("Stopped tracking {} sessions", sessions.spots.len()),
+        )).await;
+    }
+    say!("👋 Stopped");
+    Ok(())
+}
+
+/// Apply the config's process-wide settings and announce what it monitors, returning
+/// its notifiers
+fn apply_config(config: &Config) -> Vec<NotifierEntry> {
+    style::set_plain(config.plain_text);
+    retry::set_policy(config.retry);
+    parse::set_spot_patterns(config.spot_patterns.iter().map(|pattern| pattern.0.clone()).collect());
+    say!("🔄 Checking every {} seconds", config.interval_seconds);
+    for burst in &config.burst_windows {
+        say!(
+            "⚡ Checking every {} seconds from {} to {}",
+            burst.interval_seconds, burst.hours.start.format("%H:%M"), burst.hours.end.format("%H:%M"),
+        );
+    }
+    let notifiers = config.notifiers();
+    for notifier in &notifiers {
+        say!("🔔 Notifications will be sent to {}", notifier);
+    }
+    say!("📋 Monitoring {} programs", config.program_ids.len());
+    if !config.users.is_empty() {
+        say!("👥 Sharing changes with {} users", config.users.len());
+    }
+    if !config.enable_notifications {
+        say!("🔇 Notifications are disabled by enable_notifications");
+    }
+    notifiers
+}
+
+/// The notifiers of every given program, each only once
+fn routes_for(
+    routes: &HashMap<Arc<str>, Vec<NotifierEntry>>,
+    fallback: &[NotifierEntry],
+    program_ids: &[&Arc<str>],
+) -> Vec<NotifierEntry> {
+    let mut entries: Vec<NotifierEntry> = Vec::new();
+    for id in program_ids {
+        for entry in routes.get(*id).map_or(fallback, Vec::as_slice) {
+            if !entries.iter().any(|known| known.notifier == entry.notifier) {
+                entries.push(entry.clone());
+            }
+        }
+    }
+    entries
+}
+
+/// The users who want to hear about `spot` at time of day `now`; nobody through quiet hours
+fn users_wanting<'a>(config: &'a Config, spot: &SpotInfo, quiet: bool, now: NaiveTime) -> Vec<&'a UserConfig> {
+    if quiet {
+        return Vec::new();
+    }
+    config.users.iter().filter(|user| user.wants(spot, now)).collect()
+}
+
+/// Where each program's alerts go, by program ID
+fn program_routes(config: &Config) -> HashMap<Arc<str>, Vec<NotifierEntry>> {
+    config
+        .program_ids
+        .iter()
+        .map(|program| {
+            if let Some(endpoint) = &program.ntfy_endpoint {
+                say!("🔔 {} notifications will be sent to ntfy {}", program.label(), endpoint);
+            }
+            (program.id.clone(), config.program_notifiers(program))
+        })
+        .collect()
+}
+
+fn load_friends(config: &Config) -> Vec<config::FriendConfig> {
+    friends::load(config).unwrap_or_else(|e| {
+        eprintln!("Failed to load friends, continuing without them: {}", e);
+        config.friends.clone()
+    })
+}
+
+fn check_slots(config: &Config) -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(config.max_concurrent_checks.map_or(Semaphore::MAX_PERMITS, |max| max.max(1))))
+}
+
+/// When each cron-scheduled program is first due from `now`
+fn cron_schedule(config: &Config, now: DateTime<Tz>) -> HashMap<Arc<str>, DateTime<Tz>> {
+    let mut due = HashMap::new();
+    for program in &config.program_ids {
+        if let Some(cron) = &program.cron {
+            say!("🗓️ Checking {} on cron schedule \"{}\"", program.label(), cron);
+            due.extend(cron.next_from(now).map(|next| (program.id.clone(), next)));
+        }
+    }
+    due
+}
+
+/// Print one line per timeslot comparing every program in the group,
+/// e.g. "2025-03-14 19:00:00: PAC full, CIF 2 spots left"
+fn print_comparison(comparison: &ComparisonConfig, spots: &HashMap<String, SpotInfo>) {
+    let mut timeslots: std::collections::BTreeMap<(&str, &str), Vec<&SpotInfo>> = Default::default();
+    for spot in spots.values().filter(|spot| comparison.includes(spot)) {
+        timeslots.entry((&spot.date, &spot.time)).or_default().push(spot);
+    }
+
+    say!("📊 {}", comparison.name);
+    for ((date, time), mut sessions) in timeslots {
+        sessions.sort_by(|a, b| (&a.location, &a.program_name).cmp(&(&b.location, &b.program_name)));
+        let summary = sessions
+            .iter()
+            .map(|spot| {
+                let label = if spot.location.is_empty() { spot.programs_label() } else { spot.location.to_string() };
+                format!("{} {}{}", label, spot.spots.to_lowercase(), if spot.stale { " (stale)" } else { "" })
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        say!("   {} {}: {}", date, time, summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::merged_session;
+
+    #[test]
+    fn merged_sessions_route_to_every_listing_program() {
+        let (config, _, session) = merged_session();
+        let routes = program_routes(&config);
+        let ids: Vec<&Arc<str>> = session.program_ids().collect();
+        let everyone: Vec<String> = routes_for(&routes, &config.notifiers(), &ids).iter().map(ToString::to_string).collect();
+        assert_eq!(everyone, ["ntfy https://ntfy.sh/topic", "ntfy https://ntfy.sh/roommate"]);
+
+        // A spot opening up is only for Beginner, filling up for both
+        let opening: Vec<&Arc<str>> = session.program_ids().filter(|id| config.notify_on(id).allows("Full", "1 spot left")).collect();
+        assert_eq!(opening, [&Arc::<str>::from("program")]);
+        let filling: Vec<&Arc<str>> = session.program_ids().filter(|id| config.notify_on(id).allows("1 spot left", "Full")).collect();
+        let badminton: Vec<String> = routes_for(&routes, &config.notifiers(), &filling[1..]).iter().map(ToString::to_string).collect();
+        assert_eq!(badminton, ["ntfy https://ntfy.sh/roommate"]);
+    }
+}
@@ -1,316 +1,192 @@
-use anyhow::Result;
-use reqwest::Client;
-use scraper::{Html, Selector};
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::fs;
-use std::time::Duration;
-use tokio::time;
-
-const GET_URL: &str = "https://warrior.uwaterloo.ca/Program/GetProgramInstances";
-const FILTER_URL: &str = "https://warrior.uwaterloo.ca/Program/FilterProgramInstances";
-
-// Config struct to parse config.toml
-#[derive(Debug, Deserialize)]
-struct Config {
-    interval_seconds: u64,
-    ntfy_endpoint: String,
-    program_ids: Vec<ProgramConfig>,
+use clap::{ArgAction, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use uwtennis::config::{RuntimeConfig, RuntimeFlavor};
+use uwtennis::mirror::Snapshot;
+use uwtennis::{Config, Error, Result, check_all, journal, reload, run_once, run_until, say, shutdown};
+
+/// Watch UW Warrior tennis programs for open spots and notify about changes
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Config file [default: config.toml in the platform config directory, then the working directory]
+    #[arg(short, long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+    /// Run a single check of every program, save the state and exit
+    #[arg(long)]
+    once: bool,
+    /// Seconds between checks, overriding interval_seconds
+    #[arg(long, value_name = "SECONDS")]
+    interval: Option<u64>,
+    /// Print more about each check
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Print only errors
+    #[arg(short, long, conflicts_with = "verbose", global = true)]
+    quiet: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ProgramConfig {
-    id: String,
-    name: String,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Check every program once and print a table of its sessions
+    Check {
+        /// Print JSON instead, in the same shape as the mirror's availability.json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Mark a tracked session as attended
+    Attend {
+        /// Session date, e.g. 2025-03-14
+        date: String,
+        /// Session start time, e.g. 18:00
+        time: String,
+        /// Program name, when several have a session then
+        program: Option<String>,
+    },
+    /// Print attended sessions per month
+    Journal,
+    /// Print money spent per month on each program
+    Spending {
+        /// Print CSV instead
+        #[arg(long)]
+        csv: bool,
+    },
 }
 
-/// Default fields carried over in each appointment payload
-fn default_fields() -> HashMap<&'static str, &'static str> {
-    [
-        ("RecurrenceInfo", ""),
-        ("AppointmentType", "0"),
-        ("Subject", ""),
-        ("AllDay", "false"),
-        ("ResourceId", ""),
-        ("Status", "0"),
-        ("ProductId", "00000000-0000-0000-0000-000000000000"),
-        ("ProgramDescription", ""),
-        ("ProgramInstanceId", "00000000-0000-0000-0000-000000000000"),
-        ("NumberRegistered", "0"),
-        ("NumberOnWaitlist", "0"),
-        ("ClassSize", "12"),
-        ("PortalURL", ""),
-        ("InstructorFirstNameLastInitial", ""),
-        ("IsInstructor", "false"),
-        ("InstructorId", "00000000-0000-0000-0000-000000000000"),
-        ("IsRecurring", "false"),
-    ]
-    .into()
-}
-
-#[derive(Debug, Deserialize, Clone)]
-struct Appointment {
-    #[serde(rename = "ID")]
-    id: String,
-
-    #[serde(rename = "StartDate")]
-    start_date: String,
-
-    #[serde(rename = "EndDate")]
-    end_date: String,
-
-    #[serde(rename = "Location")]
-    location: String,
-
-    #[serde(rename = "ProductName")]
-    product_name: String,
-
-    // ...
-}
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    // JSON output keeps stdout to itself
+    let quiet = cli.quiet || matches!(cli.command, Some(Command::Check { json: true }));
+    uwtennis::style::set_verbosity(if quiet { -1 } else { cli.verbose as i8 });
 
-// Information about a specific appointment spot
-#[derive(Debug, Clone)]
-struct SpotInfo {
-    program_name: String,
-    product_name: String,
-    date: String,
-    time: String,
-    spots: String,
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
     // Load configuration
-    let config_text = fs::read_to_string("config.toml")?;
-    let config: Config = toml::from_str(&config_text)?;
-    println!("🔄 Checking every {} seconds", config.interval_seconds);
-    println!("🔔 Notifications will be sent to {}", config.ntfy_endpoint);
-    println!("📋 Monitoring {} programs", config.program_ids.len());
-
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:138.0)")
-        .default_headers({
-            let mut h = reqwest::header::HeaderMap::new();
-            h.insert("Accept", "*/*".parse()?);
-            h.insert(
-                "Content-Type",
-                "application/x-www-form-urlencoded; charset=UTF-8"
-                    .parse()?,
-            );
-            h.insert("X-Requested-With", "XMLHttpRequest".parse()?);
-            h.insert("Origin", "https://warrior.uwaterloo.ca".parse()?);
-            h
-        })
-        .build()?;
-
-    // Track previous spots to detect changes
-    let mut previous_spots: HashMap<String, SpotInfo> = HashMap::new();
-    
-    // Main loop for periodic checking
-    let mut interval = time::interval(Duration::from_secs(config.interval_seconds));
-    loop {
-        interval.tick().await;
-        println!("⏱️ Checking for spot changes...");
-        
-        // Process each program ID concurrently
-        let mut tasks = Vec::new();
-        for program in &config.program_ids {
-            let client = client.clone();
-            let program_id = program.id.clone();
-            let program_name = program.name.clone();
-            let ntfy_endpoint = config.ntfy_endpoint.clone();
-            
-            let task = tokio::spawn(async move {
-                match check_program(&client, &program_id, &program_name, &ntfy_endpoint).await {
-                    Ok(current_spots) => current_spots,
-                    Err(e) => {
-                        eprintln!("Error checking program {}: {}", program_name, e);
-                        HashMap::new()
-                    }
-                }
-            });
-            
-            tasks.push(task);
+    let config_path = cli.config.unwrap_or_else(default_config_path);
+    say!("⚙️ Loading config from {}", config_path.display());
+    let mut config = reload::load(&config_path)?;
+    uwtennis::style::set_plain(config.plain_text);
+    let interval = cli.interval;
+    let overrides = move |config: &mut Config| {
+        if let Some(interval) = interval {
+            config.interval_seconds = interval;
         }
-        
-        // Wait for all tasks to complete and process results
-        for task in tasks {
-            if let Ok(current_spots) = task.await {
-                for (key, spot_info) in current_spots {
-                    // Check if spots have changed
-                    if let Some(prev_info) = previous_spots.get(&key) {
-                        if prev_info.spots != spot_info.spots {
-                            println!("🔄 Change detected: {} ({}) on {} @ {} - {} → {}", 
-                                spot_info.program_name, spot_info.product_name, 
-                                spot_info.date, spot_info.time, 
-                                prev_info.spots, spot_info.spots);
-                            
-                            // Send notification
-                            let _ = send_notification(
-                                &config.ntfy_endpoint,
-                                &format!("Spot change: {}", spot_info.product_name),
-                                &format!("{} ({}) on {} @ {}: {} → {}", 
-                                    spot_info.program_name, spot_info.product_name, 
-                                    spot_info.date, spot_info.time, 
-                                    prev_info.spots, spot_info.spots)
-                            ).await;
-                        }
-                    } else {
-                        // First time seeing this appointment
-                        println!("📌 New tracking: {} ({}) on {} @ {} - {}", 
-                            spot_info.program_name, spot_info.product_name, 
-                            spot_info.date, spot_info.time, spot_info.spots);
-                    }
-                    
-                    // Update previous spots
-                    previous_spots.insert(key, spot_info);
-                }
+    };
+    overrides(&mut config);
+
+    match cli.command {
+        // The runtime is built by hand so its shape can come from the config
+        None if cli.once => build_runtime(&config.runtime)?.block_on(run_once(Arc::new(config))),
+        None => build_runtime(&config.runtime)?.block_on(async {
+            let reloads = reload::watch_with(config_path, overrides);
+            run_until(Arc::new(config), shutdown::listen(), reloads).await
+        }),
+        Some(Command::Check { json }) => {
+            let snapshot = build_runtime(&config.runtime)?.block_on(check_all(Arc::new(config)))?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&snapshot)?);
+            } else {
+                print_table(&snapshot);
             }
+            Ok(())
         }
+        Some(Command::Attend { date, time, program }) => attend(&config, &date, &time, program.as_deref()),
+        Some(Command::Journal) => print_journal(&config),
+        Some(Command::Spending { csv }) => print_spending(&config, csv),
     }
 }
 
-async fn check_program(
-    client: &Client, 
-    program_id: &str,
-    program_name: &str,
-    ntfy_endpoint: &str
-) -> Result<HashMap<String, SpotInfo>> {
-    // Fetch the data for this program
-    let (appts, dates) = fetch_initial(client, program_id).await?;
-    let mut current_spots = HashMap::new();
-    
-    for date_iso in dates {
-        if let Some(appt) = appts.iter().find(|a| a.start_date.starts_with(&date_iso[..10])) {
-            let spots = fetch_spots(client, appt, &date_iso).await?;
-            let date = &date_iso[..10];
-            let time = appt.start_date.split('T').nth(1).unwrap_or("").to_string();
-            let key = format!("{}-{}-{}", program_id, date, appt.id);
-            
-            current_spots.insert(key, SpotInfo {
-                program_name: program_name.to_string(),
-                product_name: appt.product_name.clone(),
-                date: date.to_string(),
-                time,
-                spots,
-            });
-        }
-    }
-    
-    Ok(current_spots)
+/// The platform config directory's `config.toml` if there is one, else the
+/// working directory's
+fn default_config_path() -> PathBuf {
+    directories::ProjectDirs::from("", "", "uwtennis")
+        .map(|dirs| dirs.config_dir().join("config.toml"))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| "config.toml".into())
 }
 
-async fn send_notification(endpoint: &str, title: &str, message: &str) -> Result<()> {
-    let client = Client::new();
-    let response = client.post(endpoint)
-        .header("Title", title)
-        .body(message.to_string())
-        .send()
-        .await?;
-        
-    if response.status().is_success() {
-        println!("✅ Notification sent successfully");
-    } else {
-        println!("❌ Failed to send notification: {}", response.status());
+fn build_runtime(runtime: &RuntimeConfig) -> Result<tokio::runtime::Runtime> {
+    let mut builder = match runtime.flavor {
+        RuntimeFlavor::MultiThread => tokio::runtime::Builder::new_multi_thread(),
+        RuntimeFlavor::CurrentThread => tokio::runtime::Builder::new_current_thread(),
+    };
+    if let Some(worker_threads) = runtime.worker_threads {
+        builder.worker_threads(worker_threads.get());
     }
-    
-    Ok(())
+    Ok(builder.enable_all().build()?)
 }
 
-async fn fetch_initial(
-    client: &Client,
-    program_id: &str,
-) -> Result<(Vec<Appointment>, Vec<String>)> {
-    let res = client
-        .get(GET_URL)
-        .query(&[("programID", program_id)])
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&res);
-
-    // Extract and parse appointments JSON
-    let appt_sel = Selector::parse("input#ApptInfo").unwrap();
-    let raw_appts = document
-        .select(&appt_sel)
-        .next()
-        .and_then(|e| e.value().attr("value"))
-        .ok_or_else(|| anyhow::anyhow!("Missing #ApptInfo input"))?;
-    let appts: Vec<Appointment> = serde_json::from_str(raw_appts)?;
-
-    // Extract and parse dates JSON
-    let dates_sel = Selector::parse("input#hdnDates").unwrap();
-    let raw_dates = document
-        .select(&dates_sel)
-        .next()
-        .and_then(|e| e.value().attr("value"))
-        .ok_or_else(|| anyhow::anyhow!("Missing #hdnDates input"))?;
-    let dates: Vec<String> = serde_json::from_str(raw_dates)?;
-
-    Ok((appts, dates))
+/// Print one aligned row per session, soonest first
+fn print_table(snapshot: &Snapshot) {
+    let mut rows = vec![["Program", "Product", "Date", "Time", "Spots"].map(String::from)];
+    rows.extend(snapshot.sessions.iter().map(|session| {
+        [
+            session.program.clone(),
+            session.product.clone(),
+            session.date.clone(),
+            session.time.get(..5).unwrap_or(&session.time).to_string(),
+            session.spots.clone(),
+        ]
+    }));
+    let widths: Vec<usize> =
+        (0..5).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{cell:width$}")).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+    if snapshot.sessions.is_empty() {
+        println!("No sessions found");
+    }
 }
 
-async fn fetch_spots(
-    client: &Client,
-    appt: &Appointment,
-    date_iso: &str,
-) -> Result<String> {
-    // Build form data
-    let mut form = HashMap::new();
-    let prefix = "appointments[0]";
+fn journal_path(config: &Config) -> Result<&Path> {
+    config
+        .journal_path
+        .as_deref()
+        .map(Path::new)
+        .ok_or_else(|| Error::Config("set journal_path to keep an attendance journal".into()))
+}
 
-    form.insert(format!("{prefix}[ID]"), appt.id.clone());
-    form.insert(format!("{prefix}[StartDate]"), appt.start_date.clone());
-    form.insert(format!("{prefix}[EndDate]"), appt.end_date.clone());
-    form.insert(format!("{prefix}[Location]"), appt.location.clone());
-    form.insert(
-        format!("{prefix}[ProductName]"),
-        appt.product_name.clone(),
-    );
+/// Mark a tracked session as attended
+fn attend(config: &Config, date: &str, time: &str, name: Option<&str>) -> Result<()> {
+    let spot = journal::find_session(config, date, time, name)?;
+    journal::record(journal_path(config)?, &journal::JournalEntry::new(&spot))?;
+    say!("🎾 Attended {} ({}) on {} @ {}", spot.program_name, spot.product_name, spot.date, spot.time);
+    Ok(())
+}
 
-    for (k, v) in default_fields() {
-        form.insert(format!("{prefix}[{k}]"), v.to_string());
+/// Print attended sessions per month
+fn print_journal(config: &Config) -> Result<()> {
+    let entries = journal::load(journal_path(config)?)?;
+    for (month, programs) in journal::monthly_totals(&entries) {
+        let total: usize = programs.values().sum();
+        let breakdown = programs
+            .iter()
+            .map(|(program, count)| format!("{program} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        say!("📅 {}: {} sessions ({})", month, total, breakdown);
     }
+    Ok(())
+}
 
-    // Date parts
-    let date = &date_iso[..10];
-    let parts: Vec<&str> = date.split('-').collect();
-    let year = parts[0];
-    let month = parts[1].trim_start_matches('0');
-    let day = parts[2].trim_start_matches('0');
-
-    // Use the program ID from the appointment context
-    let program_id = match appt.id.split('-').next() {
-        Some(id) => id,
-        None => return Ok("Error: Invalid ID format".into()),
-    };
-
-    form.insert("programID".into(), program_id.into());
-    form.insert("year".into(), year.into());
-    form.insert("month".into(), month.into());
-    form.insert("day".into(), day.into());
-
-    // POST and parse response
-    let res = client
-        .post(FILTER_URL)
-        .form(&form)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&res);
-    let sel_str = format!(
-        "div[data-instance-appointmentid='{}'] .spots-tag",
-        appt.id
-    );
-    let spot_sel = Selector::parse(&sel_str).unwrap();
+/// Print money spent per month on each program, optionally as CSV
+fn print_spending(config: &Config, csv: bool) -> Result<()> {
+    let spend = journal::monthly_spend(&journal::load(journal_path(config)?)?);
+    if csv {
+        print!("{}", journal::spend_csv(&spend));
+        return Ok(());
+    }
 
-    if let Some(el) = document.select(&spot_sel).next() {
-        Ok(el.text().collect::<String>().trim().to_string())
-    } else {
-        Ok("N/A".into())
+    for (month, programs) in spend {
+        let total: f64 = programs.values().map(|spend| spend.total).sum();
+        let breakdown = programs
+            .iter()
+            .map(|(program, spend)| format!("{program} ${:.2}", spend.total))
+            .collect::<Vec<_>>()
+            .join(", ");
+        say!("💵 {}: ${:.2} ({})", month, total, breakdown);
     }
-}
\ No newline at end of file
+    Ok(())
+}
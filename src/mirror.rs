@@ -0,0 +1,302 @@
+//! A read-only public mirror: the current availability written out as static JSON and
+//! HTML files, optionally pushed to a git repository serving them, and a git history
+//! of every change in availability
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::{GitHistoryConfig, MirrorConfig};
+use crate::error::{Error, Result};
+use crate::parse::{Availability, SpotInfo, classify_spots};
+
+pub const JSON_FILE: &str = "availability.json";
+pub const HTML_FILE: &str = "index.html";
+
+// What a public page gets to know about one session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MirroredSession {
+    pub program: String,
+    pub product: String,
+    pub location: String,
+    pub date: String,
+    pub time: String,
+    pub end_time: String,
+    pub spots: String,
+    pub open: bool,
+    pub availability: Option<Availability>,
+    pub stale: bool,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub generated_at: NaiveDateTime,
+    pub sessions: Vec<MirroredSession>,
+}
+
+impl Snapshot {
+    /// Every tracked session, soonest first
+    pub fn new(spots: &HashMap<String, SpotInfo>, generated_at: NaiveDateTime) -> Self {
+        let mut sessions: Vec<MirroredSession> = spots
+            .values()
+            .map(|spot| MirroredSession {
+                program: spot.programs_label(),
+                product: spot.product_name.to_string(),
+                location: spot.location.to_string(),
+                date: spot.date.to_string(),
+                time: spot.time.to_string(),
+                end_time: spot.end_time.to_string(),
+                spots: spot.spots.clone(),
+                open: classify_spots(&spot.spots).is_open(),
+                availability: spot.availability(),
+                stale: spot.stale,
+                url: spot.url(),
+            })
+            .collect();
+        sessions.sort_by(|a, b| (&a.date, &a.time, &a.program).cmp(&(&b.date, &b.time, &b.program)));
+        Snapshot { generated_at, sessions }
+    }
+
+    /// A self-contained page with one row per session
+    pub fn html(&self, title: &str) -> String {
+        let rows: String = self
+            .sessions
+            .iter()
+            .map(|session| {
+                format!(
+                    "<tr class=\"{}\"><td>{} {}</td><td>{}</td><td>{}</td><td>{}</td><td><a href=\"{}\">{}</a>{}</td></tr>\n",
+                    if session.open { "open" } else { "closed" },
+                    escape_html(&session.date),
+                    escape_html(session.time.get(..5).unwrap_or(&session.time)),
+                    escape_html(&session.program),
+                    escape_html(&session.product),
+                    escape_html(&session.location),
+                    escape_html(&session.url),
+                    escape_html(&session.spots),
+                    if session.stale { " (stale)" } else { "" },
+                )
+            })
+            .collect();
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\n\
+             <style>body{{font-family:sans-serif}}td{{padding:2px 8px}}.open{{font-weight:bold}}</style></head>\n\
+             <body><h1>{title}</h1><p>Updated {}</p>\n<table>\n\
+             <tr><th>When</th><th>Program</th><th>Session</th><th>Where</th><th>Spots</th></tr>\n{rows}</table></body></html>\n",
+            self.generated_at.format("%Y-%m-%d %H:%M"),
+            title = escape_html(title),
+        )
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Writes the mirror each cycle, remembering what it last pushed
+#[derive(Debug)]
+pub struct Mirror {
+    config: MirrorConfig,
+    pushed: Option<Vec<MirroredSession>>,
+}
+
+impl Mirror {
+    pub fn new(config: MirrorConfig) -> Self {
+        Mirror { config, pushed: None }
+    }
+
+    /// Write the snapshot's files, then commit and push them when availability changed
+    pub async fn publish(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let dir = Path::new(&self.config.dir);
+        fs::create_dir_all(dir)?;
+        if self.config.json {
+            write_atomically(&dir.join(JSON_FILE), &serde_json::to_vec_pretty(snapshot)?)?;
+        }
+        if self.config.html {
+            write_atomically(&dir.join(HTML_FILE), snapshot.html(&self.config.title).as_bytes())?;
+        }
+
+        // Timestamps change every cycle; only new availability is worth a commit
+        if !self.config.git_push || self.pushed.as_ref() == Some(&snapshot.sessions) {
+            return Ok(());
+        }
+        let dir = self.config.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            git(&dir, &["add", "-A"])?;
+            // Nothing to commit is fine, e.g. right after a restart
+            if git(&dir, &["diff", "--cached", "--quiet"]).is_err() {
+                commit(&dir, "Update availability")?;
+                git(&dir, &["push", "-q"])?;
+            }
+            Ok::<_, Error>(())
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        self.pushed = Some(snapshot.sessions.clone());
+        Ok(())
+    }
+}
+
+// Commits each change in availability to a local git repository, so the whole term's
+// schedule can be browsed and diffed
+#[derive(Debug)]
+pub struct GitHistory {
+    config: GitHistoryConfig,
+    committed: Option<Vec<MirroredSession>>,
+}
+
+impl GitHistory {
+    pub fn new(config: GitHistoryConfig) -> Self {
+        GitHistory { config, committed: None }
+    }
+
+    /// Commit the snapshot's sessions if they differ from the last commit, creating the
+    /// repository on first use
+    pub async fn record(&mut self, snapshot: &Snapshot) -> Result<()> {
+        if self.committed.as_ref() == Some(&snapshot.sessions) {
+            return Ok(());
+        }
+        let dir = self.config.dir.clone();
+        let push = self.config.push;
+        // Only the sessions, so commits are about availability rather than timestamps
+        let contents = serde_json::to_vec_pretty(&snapshot.sessions)?;
+        let message = format!("Availability at {}", snapshot.generated_at.format("%Y-%m-%d %H:%M"));
+        tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&dir)?;
+            if !Path::new(&dir).join(".git").exists() {
+                git(&dir, &["init", "-q"])?;
+            }
+            write_atomically(&Path::new(&dir).join(JSON_FILE), &contents)?;
+            git(&dir, &["add", JSON_FILE])?;
+            if git(&dir, &["diff", "--cached", "--quiet"]).is_err() {
+                commit(&dir, &message)?;
+                if push {
+                    git(&dir, &["push", "-q"])?;
+                }
+            }
+            Ok::<_, Error>(())
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        self.committed = Some(snapshot.sessions.clone());
+        Ok(())
+    }
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Commit what's staged in `dir` as uwtennis, so the host needs no git identity set up
+fn commit(dir: &str, message: &str) -> Result<()> {
+    git(dir, &["-c", "user.name=uwtennis", "-c", "user.email=uwtennis@localhost", "commit", "-q", "-m", message])
+}
+
+/// Run git in `dir`, failing with its stderr when it does
+fn git(dir: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::Io(std::io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::spot;
+
+    #[tokio::test]
+    async fn mirror_writes_public_snapshot_files() {
+        let dir = std::env::temp_dir().join(format!("uwtennis-mirror-{}", std::process::id()));
+        let mirror_config: crate::config::MirrorConfig =
+            toml::from_str(&format!("dir = \"{}\"\ntitle = \"Courts <today>\"", dir.display())).unwrap();
+        let mut later = spot("Beginner", "Full");
+        later.time = "21:00:00".into();
+        later.stale = true;
+        let mut evening = spot("Tom & Jerry's", "2 spots left");
+        evening.portal_url = Some("https://example.com/a".into());
+        let spots = HashMap::from([("b".to_string(), later), ("a".to_string(), evening)]);
+        let now = chrono::NaiveDateTime::parse_from_str("2025-03-14 12:00", "%Y-%m-%d %H:%M").unwrap();
+
+        Mirror::new(mirror_config).publish(&Snapshot::new(&spots, now)).await.unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(JSON_FILE)).unwrap()).unwrap();
+        assert_eq!(json["sessions"][0]["time"], "19:00:00");
+        assert_eq!(json["sessions"][0]["open"], true);
+        assert_eq!(json["sessions"][0]["url"], "https://example.com/a");
+        assert_eq!(json["sessions"][1]["stale"], true);
+
+        let html = std::fs::read_to_string(dir.join(HTML_FILE)).unwrap();
+        assert!(html.contains("<title>Courts &lt;today&gt;</title>"));
+        assert!(html.contains("Tom &amp; Jerry&#39;s"));
+        assert!(html.contains("<a href=\"https://example.com/a\">2 spots left</a>"));
+        assert!(html.contains("Full</a> (stale)"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn git_history_commits_only_changes() {
+        let dir = std::env::temp_dir().join(format!("uwtennis-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut history = GitHistory::new(toml::from_str(&format!("dir = \"{}\"", dir.display())).unwrap());
+        let at = |time: &str| chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        let commits = || {
+            let output = std::process::Command::new("git").arg("-C").arg(&dir).args(["rev-list", "--count", "HEAD"]).output().unwrap();
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        };
+
+        let full = HashMap::from([("a".to_string(), spot("Beginner", "Full"))]);
+        history.record(&Snapshot::new(&full, at("2025-03-14 18:00"))).await.unwrap();
+        // Same availability a cycle later: nothing to commit
+        history.record(&Snapshot::new(&full, at("2025-03-14 18:01"))).await.unwrap();
+        assert_eq!(commits(), "1");
+
+        let open = HashMap::from([("a".to_string(), spot("Beginner", "1 spot left"))]);
+        history.record(&Snapshot::new(&open, at("2025-03-14 18:02"))).await.unwrap();
+        assert_eq!(commits(), "2");
+        let file = std::fs::read_to_string(dir.join(JSON_FILE)).unwrap();
+        assert!(file.contains("1 spot left") && !file.contains("generated_at"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn mirror_pushes_as_uwtennis() {
+        let root = std::env::temp_dir().join(format!("uwtennis-mirror-push-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let (remote, dir) = (root.join("remote.git"), root.join("checkout"));
+        let run = |args: &[&str]| {
+            let output = std::process::Command::new("git").args(args).output().unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        };
+        run(&["init", "-q", "--bare", remote.to_str().unwrap()]);
+        run(&["clone", "-q", remote.to_str().unwrap(), dir.to_str().unwrap()]);
+        let mirror_config: crate::config::MirrorConfig =
+            toml::from_str(&format!("dir = \"{}\"\ngit_push = true", dir.display())).unwrap();
+
+        let spots = HashMap::from([("a".to_string(), spot("Beginner", "2 spots left"))]);
+        let now = chrono::NaiveDateTime::parse_from_str("2025-03-14 12:00", "%Y-%m-%d %H:%M").unwrap();
+        Mirror::new(mirror_config).publish(&Snapshot::new(&spots, now)).await.unwrap();
+
+        let author = run(&["-C", remote.to_str().unwrap(), "log", "-1", "--format=%an <%ae>"]);
+        assert_eq!(author, "uwtennis <uwtennis@localhost>");
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,1269 @@
+//! Delivering spot changes: ntfy, the other chat backends and CalDAV
+
+use base64::prelude::*;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::clock::FACILITY_TZ;
+use crate::config::{
+    CalDavConfig, DiscordConfig, EmailConfig, IssueTrackerConfig, NotifierConfig, NotifierEntry, NtfyConfig, Secret,
+    SignalConfig, TeamsConfig, WebhookConfig, WhatsAppConfig,
+};
+use crate::error::{Error, Result};
+use crate::fetch::parse_retry_after;
+use crate::parse::{SpotInfo, classify_spots, compare_availability};
+use crate::retry::{self, with_retry};
+use crate::style;
+
+// Message size limits per backend, in bytes
+pub const NTFY_MAX_BYTES: usize = 4096;
+pub const SIGNAL_MAX_BYTES: usize = 2000;
+pub const WHATSAPP_MAX_BYTES: usize = 1024;
+pub const ISSUE_MAX_BYTES: usize = 65536;
+// Discord embed descriptions are capped at 4096 characters, titles at 256
+pub const DISCORD_DESCRIPTION_MAX_BYTES: usize = 4096;
+pub const DISCORD_TITLE_MAX_BYTES: usize = 256;
+// Discord poll questions are capped at 300 characters, answers at 55
+pub const DISCORD_POLL_QUESTION_MAX_BYTES: usize = 300;
+pub const DISCORD_POLL_ANSWER_MAX_BYTES: usize = 55;
+
+impl Notifier for TeamsConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        send_teams(self, notification).await
+    }
+}
+
+impl Notifier for WhatsAppConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        // Template parameters may not contain newlines
+        let text = notification.message.replace('\n', " - ");
+        let parts = self
+            .recipients
+            .iter()
+            .flat_map(|recipient| split_message(&text, WHATSAPP_MAX_BYTES).into_iter().map(move |part| (recipient, part)));
+        for (recipient, part) in parts.skip(delivered.parts()) {
+            send_whatsapp(self, recipient, &part.replace('\n', " ")).await?;
+            delivered.count_part();
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for SignalConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        let text = format!("{}\n{}", notification.title, notification.message);
+        for part in split_message(&text, SIGNAL_MAX_BYTES).into_iter().skip(delivered.parts()) {
+            send_signal(self, &part).await?;
+            delivered.count_part();
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for IssueTrackerConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        // Issues are rendered as Markdown, so plain text is escaped to come out as written
+        let message =
+            if notification.markdown { notification.message.clone() } else { escape_markdown(&notification.message) };
+        for body in split_message(&message, ISSUE_MAX_BYTES).into_iter().skip(delivered.parts()) {
+            send_issue_event(self, &notification.title, &body).await?;
+            delivered.count_part();
+        }
+        Ok(())
+    }
+}
+
+/// Post an Adaptive Card with the notification to a Teams incoming webhook, laying out
+/// the session's details when it's about one
+async fn send_teams(teams: &TeamsConfig, notification: &Notification) -> Result<()> {
+    // Adaptive Card text is rendered as Markdown
+    let text = |value: &str| if notification.markdown { value.to_string() } else { escape_markdown(value) };
+    let details = match &notification.spot {
+        Some(spot) => {
+            let spots = match &notification.previous_spots {
+                Some(previous) => format!("{} → {}", escape_markdown(previous), escape_markdown(&spot.spots)),
+                None => escape_markdown(&spot.spots),
+            };
+            let mut facts = vec![
+                serde_json::json!({ "title": "Program", "value": escape_markdown(&spot.programs_label()) }),
+                serde_json::json!({ "title": "Session", "value": escape_markdown(&spot.product_name) }),
+                serde_json::json!({ "title": "Date", "value": spot.date }),
+                serde_json::json!({ "title": "Time", "value": spot.time }),
+                serde_json::json!({ "title": "Spots", "value": spots }),
+            ];
+            if let Some(membership) = &spot.membership {
+                facts.push(serde_json::json!({ "title": "Requires", "value": escape_markdown(membership) }));
+            }
+            serde_json::json!({ "type": "FactSet", "facts": facts })
+        }
+        // Digests and health updates have no one session to lay out
+        None => serde_json::json!({ "type": "TextBlock", "text": text(&notification.message), "wrap": true }),
+    };
+
+    let card = serde_json::json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": [
+                    { "type": "TextBlock", "text": escape_markdown(&notification.title), "weight": "Bolder", "size": "Medium", "wrap": true },
+                    details,
+                ],
+            },
+        }],
+    });
+
+    let response = Client::new()
+        .post(&teams.webhook_url)
+        .json(&card)
+        .send()
+        .await
+        .map_err(Error::notify)?;
+    check_delivery(&response)?;
+    say!("✅ Teams message sent");
+    Ok(())
+}
+
+/// Send a template message through the WhatsApp Business Cloud API
+async fn send_whatsapp(whatsapp: &WhatsAppConfig, recipient: &str, text: &str) -> Result<()> {
+    let response = Client::new()
+        .post(format!("https://graph.facebook.com/v21.0/{}/messages", whatsapp.phone_number_id))
+        .bearer_auth(&whatsapp.token)
+        .json(&serde_json::json!({
+            "messaging_product": "whatsapp",
+            "to": recipient,
+            "type": "template",
+            "template": {
+                "name": whatsapp.template,
+                "language": { "code": whatsapp.language },
+                "components": [{
+                    "type": "body",
+                    "parameters": [{ "type": "text", "text": text }],
+                }],
+            },
+        }))
+        .send()
+        .await
+        .map_err(Error::notify)?;
+    check_delivery(&response)?;
+    say!("✅ WhatsApp message sent to {}", recipient);
+    Ok(())
+}
+
+/// Send a message through a signal-cli REST API instance
+async fn send_signal(signal: &SignalConfig, message: &str) -> Result<()> {
+    let response = Client::new()
+        .post(format!("{}/v2/send", signal.api_url.trim_end_matches('/')))
+        .json(&serde_json::json!({
+            "message": message,
+            "number": signal.number,
+            "recipients": signal.recipients,
+        }))
+        .send()
+        .await
+        .map_err(Error::notify)?;
+    check_delivery(&response)?;
+    say!("✅ Signal message sent");
+    Ok(())
+}
+
+/// Comment on the configured issue, or open a new one (GitHub and Gitea share this API)
+async fn send_issue_event(tracker: &IssueTrackerConfig, title: &str, body: &str) -> Result<()> {
+    let base = format!("{}/repos/{}/issues", tracker.api_url.trim_end_matches('/'), tracker.repo);
+    let (url, payload) = match tracker.issue {
+        Some(issue) => (format!("{base}/{issue}/comments"), serde_json::json!({ "body": body })),
+        None => (base, serde_json::json!({ "title": title, "body": body })),
+    };
+
+    let response = Client::new()
+        .post(url)
+        .header("Authorization", format!("token {}", tracker.token))
+        .header("Accept", "application/json")
+        .header("User-Agent", "uwtennis")
+        .json(&payload)
+        .send()
+        .await
+        .map_err(Error::notify)?;
+    check_delivery(&response)?;
+    say!("✅ Issue event posted to {}", tracker.repo);
+    Ok(())
+}
+
+/// Publish open sessions as events on the CalDAV calendar and remove ones that closed
+pub async fn sync_caldav(
+    client: &Client,
+    caldav: &CalDavConfig,
+    published: &mut HashMap<String, String>,
+    spots: &HashMap<String, SpotInfo>,
+) {
+    let open: HashMap<String, &SpotInfo> = spots
+        .iter()
+        .filter(|(_, spot)| classify_spots(&spot.spots).is_open())
+        .map(|(key, spot)| (event_uid(key), spot))
+        .collect();
+
+    for (uid, spot) in &open {
+        let summary = format!("{} ({}) - {}", spot.programs_label(), spot.product_name, spot.spots);
+        if published.get(uid) == Some(&summary) {
+            continue;
+        }
+
+        let request = client
+            .put(caldav_event_url(caldav, uid))
+            .header("Content-Type", "text/calendar; charset=utf-8")
+            .body(render_event(uid, spot, &summary));
+        match caldav_auth(request, caldav).send().await {
+            Ok(response) if response.status().is_success() => {
+                published.insert(uid.clone(), summary);
+            }
+            Ok(response) => eprintln!("Failed to publish CalDAV event {}: {}", uid, response.status()),
+            Err(e) => eprintln!("Failed to publish CalDAV event {}: {}", uid, e),
+        }
+    }
+
+    let closed: Vec<String> = published
+        .keys()
+        .filter(|uid| !open.contains_key(*uid))
+        .cloned()
+        .collect();
+    for uid in closed {
+        let request = client.delete(caldav_event_url(caldav, &uid));
+        match caldav_auth(request, caldav).send().await {
+            // Already gone is as good as deleted
+            Ok(response) if response.status().is_success() || response.status() == 404 => {
+                published.remove(&uid);
+            }
+            Ok(response) => eprintln!("Failed to remove CalDAV event {}: {}", uid, response.status()),
+            Err(e) => eprintln!("Failed to remove CalDAV event {}: {}", uid, e),
+        }
+    }
+}
+
+fn caldav_auth(request: reqwest::RequestBuilder, caldav: &CalDavConfig) -> reqwest::RequestBuilder {
+    match &caldav.username {
+        Some(username) => request.basic_auth(username, caldav.password.as_ref()),
+        None => request,
+    }
+}
+
+fn caldav_event_url(caldav: &CalDavConfig, uid: &str) -> String {
+    format!("{}/{}.ics", caldav.url.trim_end_matches('/'), uid)
+}
+
+/// Stable, URL-safe event UID for a session key
+fn event_uid(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Single-event iCalendar document for a session
+fn render_event(uid: &str, spot: &SpotInfo, summary: &str) -> String {
+    // In UTC, so the file needs no VTIMEZONE for the facility's zone; a time that can't
+    // be placed (unparseable, or skipped by a DST change) stays floating local time
+    let utc = |time: &str| {
+        chrono::NaiveDateTime::parse_from_str(&format!("{} {}", spot.date, time), "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .and_then(|local| local.and_local_timezone(FACILITY_TZ).earliest())
+            .map(|start| start.with_timezone(&chrono::Utc).format("%Y%m%dT%H%M%SZ").to_string())
+            .unwrap_or_else(|| format!("{}T{}", spot.date.replace('-', ""), time.replace(':', "")))
+    };
+    let end_time = if spot.end_time.is_empty() { &spot.time } else { &spot.end_time };
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+
+    [
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//uwtennis//EN".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{uid}@uwtennis"),
+        format!("DTSTAMP:{stamp}"),
+        format!("DTSTART:{}", utc(&spot.time)),
+        format!("DTEND:{}", utc(end_time)),
+        format!("SUMMARY:{}", escape_ical(summary)),
+        "END:VEVENT".to_string(),
+        "END:VCALENDAR".to_string(),
+    ]
+    .join("\r\n")
+        + "\r\n"
+}
+
+/// Escape text values per RFC 5545
+fn escape_ical(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fill `{program}`, `{product}`, `{date}`, `{time}` and `{spots}` placeholders
+pub fn render_template(template: &str, spot: &SpotInfo) -> String {
+    template
+        .replace("{program}", &spot.programs_label())
+        .replace("{product}", &spot.product_name)
+        .replace("{date}", &spot.date)
+        .replace("{time}", &spot.time)
+        .replace("{spots}", &spot.spots)
+}
+
+/// Describe a spot change, highlighting the old and new values when `markdown` is set
+/// (or spelling every field out in plain-text mode)
+pub fn format_change(prev: &SpotInfo, spot: &SpotInfo, markdown: bool) -> String {
+    if style::is_plain() {
+        return format_change_plain(prev, spot);
+    }
+    // Scraped text must not be able to inject formatting of its own
+    let text = |value: &str| if markdown { escape_markdown(value) } else { value.to_string() };
+    let change = if markdown {
+        format!("~~{}~~ → **{}** {}", text(&prev.spots), text(&spot.spots), change_arrow(&prev.spots, &spot.spots))
+    } else {
+        format!("{} → {}", prev.spots, spot.spots)
+    };
+    let requirement = spot
+        .membership
+        .as_ref()
+        .map(|membership| format!("\nRequires: {}", text(membership)))
+        .unwrap_or_default();
+    format!("{} ({}) on {} @ {}: {}{}",
+        text(&spot.programs_label()), text(&spot.product_name), spot.date, spot.time,
+        change, requirement)
+}
+
+/// A spot change with every field labelled and written out, for screen readers and SMS
+pub fn format_change_plain(prev: &SpotInfo, spot: &SpotInfo) -> String {
+    let when = match spot.start() {
+        Some(start) => {
+            let end = chrono::NaiveTime::parse_from_str(&spot.end_time, "%H:%M:%S")
+                .map(|end| format!(" to {}", end.format("%-I:%M %p")))
+                .unwrap_or_default();
+            format!("Date: {}. Time: {}{}.", start.format("%A, %B %-d, %Y"), start.format("%-I:%M %p"), end)
+        }
+        None => format!("Date: {}. Time: {}.", spot.date, spot.time),
+    };
+    let location = if spot.location.is_empty() { String::new() } else { format!(" Location: {}.", spot.location) };
+    let requirement = spot
+        .membership
+        .as_ref()
+        .map(|membership| format!(" Requires: {}.", membership))
+        .unwrap_or_default();
+    style::plain_text(&format!(
+        "Program: {}. Session: {}. {}{} Spots: was {}, now {}.{}",
+        spot.programs_label(), spot.product_name, when, location, prev.spots, spot.spots, requirement
+    ))
+}
+
+/// Backslash-escape everything Markdown could interpret as formatting
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_{}[]()<>#+-.!|~&".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Glyph showing whether availability went up or down
+fn change_arrow(previous: &str, current: &str) -> &'static str {
+    match compare_availability(previous, current) {
+        std::cmp::Ordering::Greater => "⬆️",
+        std::cmp::Ordering::Less => "⬇️",
+        std::cmp::Ordering::Equal => "🔄",
+    }
+}
+
+// A single notification, fanned out to every configured notifier
+#[derive(Debug, Clone, Default)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    // e.g. the program's emoji
+    pub tags: Vec<String>,
+    // The message is Markdown
+    pub markdown: bool,
+    // PNG screenshot, for notifiers that can attach one
+    pub attachment: Option<Vec<u8>>,
+    // The session this is about, for notifiers that lay out its details
+    pub spot: Option<SpotInfo>,
+    // Its spots text before the change
+    pub previous_spots: Option<String>,
+    // How insistently ntfy should alert; `None` leaves the server default
+    pub priority: Option<Priority>,
+}
+
+// ntfy message priorities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Min,
+    Low,
+    Default,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    /// Value of ntfy's `Priority` header
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Min => "min",
+            Priority::Low => "low",
+            Priority::Default => "default",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    /// High when a spot opens up, low when the last one disappears, default otherwise
+    pub fn for_change(previous: &str, current: &str) -> Self {
+        match (classify_spots(previous).is_open(), classify_spots(current).is_open()) {
+            (false, true) => Priority::High,
+            (true, false) => Priority::Low,
+            _ => Priority::Default,
+        }
+    }
+}
+
+impl Notification {
+    pub fn new(title: impl Into<String>, message: impl Into<String>) -> Self {
+        Notification { title: title.into(), message: message.into(), ..Default::default() }
+    }
+
+    /// The same notification stripped down for plain-text mode: no emoji tags,
+    /// no Markdown, symbols spelled out
+    pub fn plain(&self) -> Self {
+        Notification {
+            title: style::plain_text(&self.title),
+            message: style::plain_text(&self.message),
+            tags: Vec::new(),
+            markdown: false,
+            ..self.clone()
+        }
+    }
+
+    /// ntfy headers carrying the tags, Markdown flag, priority, and for a session
+    /// a click-through to its registration page plus a Register button while it's open
+    pub fn ntfy_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if !self.tags.is_empty() {
+            headers.push(("Tags", self.tags.join(",")));
+        }
+        if self.markdown {
+            headers.push(("Markdown", "yes".to_string()));
+        }
+        if let Some(priority) = self.priority {
+            headers.push(("Priority", priority.as_str().to_string()));
+        }
+        if let Some(spot) = &self.spot {
+            let url = spot.url();
+            if classify_spots(&spot.spots).is_open() {
+                headers.push(("Actions", format!("view, Register, {url}, clear=true")));
+            }
+            headers.push(("Click", url));
+        }
+        headers
+    }
+}
+
+// A destination notifications can be delivered to
+pub trait Notifier {
+    fn send(&self, notification: &Notification) -> impl Future<Output = Result<()>> + Send;
+
+    /// Like `send`, skipping the parts of a long message that `delivered` counts and
+    /// counting the ones sent now, so a retry resumes at the part that failed
+    fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> impl Future<Output = Result<()>> + Send {
+        let _ = delivered;
+        self.send(notification)
+    }
+}
+
+// How many parts of a long message have gone out, kept across retries of one send
+#[derive(Debug, Default)]
+pub struct Delivered(AtomicUsize);
+
+impl Delivered {
+    pub fn parts(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn count_part(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Turn a backend's error status into an error: 5xx, 408 and 429 are retried like
+/// the site's, anything else is a rejection not worth repeating
+pub(crate) fn check_delivery(response: &reqwest::Response) -> Result<()> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let url = response.url().to_string();
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        return Err(Error::RateLimited { url, retry_after });
+    }
+    Err(Error::Status { status, url })
+}
+
+impl NtfyConfig {
+    /// `Authorization` header value for the configured token or username and password
+    pub fn authorization(&self) -> Result<Option<String>> {
+        if let Some(token) = &self.token {
+            return Ok(Some(format!("Bearer {}", token.resolve()?)));
+        }
+        let Some(username) = &self.username else {
+            return Ok(None);
+        };
+        let password = self.password.as_ref().map(Secret::resolve).transpose()?.unwrap_or_default();
+        Ok(Some(format!("Basic {}", BASE64_STANDARD.encode(format!("{username}:{password}")))))
+    }
+}
+
+impl Notifier for NtfyConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        let mut headers = notification.ntfy_headers();
+        if let Some(authorization) = self.authorization()? {
+            headers.push(("Authorization", authorization));
+        }
+        match &notification.attachment {
+            Some(png) => send_notification_with_attachment(
+                &self.endpoint, &notification.title, &notification.message, png.clone(), &headers,
+            ).await,
+            None => post_parts(&self.endpoint, &notification.title, &notification.message, &headers, delivered).await,
+        }
+    }
+}
+
+impl Notifier for WebhookConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut request = Client::new().post(&self.url).json(&serde_json::json!({
+            "title": notification.title,
+            "message": notification.message,
+            "tags": notification.tags,
+            "markdown": notification.markdown,
+            "availability": notification.spot.as_ref().and_then(SpotInfo::availability),
+            "observed_at": notification.spot.as_ref().and_then(|spot| spot.observed_at),
+            "stale": notification.spot.as_ref().is_some_and(|spot| spot.stale),
+            "url": notification.spot.as_ref().map(SpotInfo::url),
+        }));
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.map_err(Error::notify)?;
+        check_delivery(&response)?;
+        say!("✅ Webhook notification sent to {}", self.url);
+        Ok(())
+    }
+}
+
+impl Notifier for DiscordConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        let mut fields = Vec::new();
+        let mut color = 0x5865f2;
+        let mut url = None;
+        if let Some(spot) = &notification.spot {
+            let spots = match &notification.previous_spots {
+                Some(previous) => format!("{} → {}", escape_markdown(previous), escape_markdown(&spot.spots)),
+                None => escape_markdown(&spot.spots),
+            };
+            fields.push(serde_json::json!({ "name": "Program", "value": escape_markdown(&spot.programs_label()), "inline": true }));
+            fields.push(serde_json::json!({ "name": "Date", "value": spot.date, "inline": true }));
+            fields.push(serde_json::json!({ "name": "Time", "value": spot.time, "inline": true }));
+            fields.push(serde_json::json!({ "name": "Spots", "value": spots }));
+            color = if classify_spots(&spot.spots).is_open() { 0x57f287 } else { 0xed4245 };
+            url = Some(spot.url());
+        }
+
+        // Spot details live in the fields, so only the first part carries them
+        let title = truncate(&notification.title, DISCORD_TITLE_MAX_BYTES);
+        for (index, description) in split_message(&notification.message, DISCORD_DESCRIPTION_MAX_BYTES)
+            .into_iter()
+            .enumerate()
+            .skip(delivered.parts())
+        {
+            let response = Client::new()
+                .post(&self.webhook_url)
+                .json(&serde_json::json!({
+                    "username": self.username,
+                    "embeds": [{
+                        "title": title,
+                        "description": description,
+                        "url": url,
+                        "color": color,
+                        "fields": if index == 0 { fields.clone() } else { Vec::new() },
+                    }],
+                }))
+                .send()
+                .await
+                .map_err(Error::notify)?;
+            check_delivery(&response)?;
+            delivered.count_part();
+            say!("✅ Discord message sent");
+        }
+
+        Ok(())
+    }
+}
+
+/// Post a native poll through the webhook, returning its message ID
+pub(crate) async fn send_discord_poll(discord: &DiscordConfig, question: &str, answers: &[String], hours: u64) -> Result<String> {
+    let message: serde_json::Value = Client::new()
+        .post(&discord.webhook_url)
+        .query(&[("wait", "true")])
+        .json(&serde_json::json!({
+            "username": discord.username,
+            "poll": {
+                "question": { "text": truncate(question, DISCORD_POLL_QUESTION_MAX_BYTES) },
+                "answers": answers
+                    .iter()
+                    .map(|text| serde_json::json!({ "poll_media": { "text": truncate(text, DISCORD_POLL_ANSWER_MAX_BYTES) } }))
+                    .collect::<Vec<_>>(),
+                "duration": hours.clamp(1, 768),
+                "allow_multiselect": true,
+            },
+        }))
+        .send()
+        .await
+        .map_err(Error::notify)?
+        .error_for_status()
+        .map_err(Error::notify)?
+        .json()
+        .await
+        .map_err(Error::notify)?;
+    message["id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| Error::Notify("Discord poll response has no message ID".into()))
+}
+
+/// Number of votes for each of a poll's `answers` answers, in order
+pub(crate) async fn discord_poll_counts(discord: &DiscordConfig, message_id: &str, answers: usize) -> Result<Vec<u32>> {
+    let message: serde_json::Value = Client::new()
+        .get(format!("{}/messages/{}", discord.webhook_url.trim_end_matches('/'), message_id))
+        .send()
+        .await
+        .map_err(Error::notify)?
+        .error_for_status()
+        .map_err(Error::notify)?
+        .json()
+        .await
+        .map_err(Error::notify)?;
+
+    // Answers are numbered from 1, and ones without votes are left out
+    let mut counts = vec![0; answers];
+    for answer in message["poll"]["results"]["answer_counts"].as_array().into_iter().flatten() {
+        if let (Some(id), Some(count)) = (answer["id"].as_u64(), answer["count"].as_u64())
+            && let Some(slot) = counts.get_mut((id as usize).wrapping_sub(1))
+        {
+            *slot = count as u32;
+        }
+    }
+    Ok(counts)
+}
+
+/// Cut `text` down to at most `limit` bytes on a character boundary
+fn truncate(text: &str, limit: usize) -> &str {
+    &text[..text.floor_char_boundary(limit)]
+}
+
+impl Notifier for NotifierConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        let plain;
+        let notification = if style::is_plain() {
+            plain = notification.plain();
+            &plain
+        } else {
+            notification
+        };
+        match self {
+            NotifierConfig::Ntfy(ntfy) => ntfy.send_resuming(notification, delivered).await,
+            NotifierConfig::Webhook(webhook) => webhook.send(notification).await,
+            NotifierConfig::Discord(discord) => discord.send_resuming(notification, delivered).await,
+            NotifierConfig::Telegram(telegram) => telegram.send_resuming(notification, delivered).await,
+            NotifierConfig::Email(email) => send_email(email, notification).await,
+            NotifierConfig::Signal(signal) => signal.send_resuming(notification, delivered).await,
+            NotifierConfig::WhatsApp(whatsapp) => whatsapp.send_resuming(notification, delivered).await,
+            NotifierConfig::Teams(teams) => teams.send(notification).await,
+            NotifierConfig::IssueTracker(tracker) => tracker.send_resuming(notification, delivered).await,
+            NotifierConfig::Stdout => {
+                say!("📣 {}\n{}", notification.title, notification.message);
+                Ok(())
+            }
+            NotifierConfig::Debug(debug) => debug.send(notification).await,
+        }
+    }
+}
+
+/// Email a notification over SMTP
+async fn send_email(email: &EmailConfig, notification: &Notification) -> Result<()> {
+    #[cfg(feature = "email")]
+    {
+        crate::email::send(email, notification).await
+    }
+    #[cfg(not(feature = "email"))]
+    {
+        let _ = (email, notification);
+        Err(Error::Config("email notifications require building with the `email` feature".into()))
+    }
+}
+
+/// Deliver a notification to every notifier, logging the ones that fail
+pub async fn notify_all(notifiers: &[NotifierEntry], notification: &Notification) {
+    for entry in notifiers {
+        if let Err(e) = send_with_retry(&entry.notifier, notification).await {
+            eprintln!("Failed to notify {}: {}", entry, e);
+        }
+    }
+}
+
+/// Send through one notifier, retrying failures to reach it under the configured policy
+async fn send_with_retry(notifier: &NotifierConfig, notification: &Notification) -> Result<()> {
+    let delivered = Delivered::default();
+    with_retry(&retry::policy(), &format!("Notifying {notifier}"), || notifier.send_resuming(notification, &delivered)).await
+}
+
+/// Deliver a session notification right away, except to digest notifiers, which get it
+/// as part of the cycle's summary (and to everyone when the digest holds everything)
+pub async fn notify_or_hold(notifiers: &[NotifierEntry], notification: &Notification, digest: &mut Digest) {
+    for entry in notifiers {
+        if entry.digest || digest.holds_everything {
+            digest.hold(&entry.notifier, notification);
+        } else if let Err(e) = send_with_retry(&entry.notifier, notification).await {
+            eprintln!("Failed to notify {}: {}", entry, e);
+        }
+    }
+}
+
+// Session notifications held back for digest notifiers during one cycle, or for every
+// notifier through quiet hours or the first check after a restart
+#[derive(Debug, Default)]
+pub struct Digest {
+    pending: Vec<(NotifierConfig, Vec<Notification>)>,
+    // Hold for every notifier, keeping only each session's latest notification
+    holds_everything: bool,
+    // Put in front of each summary's title
+    heading: Option<&'static str>,
+}
+
+impl Digest {
+    /// A digest that holds every session notification until it's flushed, e.g. in the morning
+    pub fn overnight() -> Self {
+        Digest { holds_everything: true, ..Default::default() }
+    }
+
+    /// A digest of where sessions ended up while the monitor wasn't running, for the
+    /// first check against the saved state
+    pub fn while_away() -> Self {
+        Digest { heading: Some("While you were away"), ..Digest::overnight() }
+    }
+
+    pub fn hold(&mut self, notifier: &NotifierConfig, notification: &Notification) {
+        let holds_everything = self.holds_everything;
+        match self.pending.iter_mut().find(|(held_for, _)| held_for == notifier) {
+            Some((_, held)) => {
+                // Overnight, only where each session ended up matters
+                if holds_everything && let Some(spot) = &notification.spot {
+                    held.retain(|earlier| earlier.spot.as_ref().is_none_or(|earlier| !earlier.same_session(spot)));
+                }
+                held.push(notification.clone())
+            }
+            None => self.pending.push((notifier.clone(), vec![notification.clone()])),
+        }
+    }
+
+    /// Number of notifications held
+    pub fn len(&self) -> usize {
+        self.pending.iter().map(|(_, held)| held.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Drop everything held without sending it
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Send each digest notifier one summary of what it was held back from
+    pub async fn flush(&mut self) {
+        for (notifier, held) in self.pending.drain(..) {
+            let mut summary = summarize(held);
+            if let Some(heading) = self.heading {
+                summary.title = format!("{}: {}", heading, summary.title);
+            }
+            if let Err(e) = send_with_retry(&notifier, &summary).await {
+                eprintln!("Failed to send digest to {}: {}", notifier, e);
+            }
+        }
+    }
+}
+
+/// Combine a cycle's notifications into one, or pass a lone one through untouched
+pub fn summarize(mut notifications: Vec<Notification>) -> Notification {
+    if notifications.len() == 1 {
+        return notifications.remove(0);
+    }
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in notifications.iter().flat_map(|notification| &notification.tags) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    let markdown = notifications.iter().all(|notification| notification.markdown);
+    let message = notifications
+        .iter()
+        .map(|notification| {
+            let title = if markdown { format!("**{}**", escape_markdown(&notification.title)) } else { notification.title.clone() };
+            format!("{}\n{}", title, notification.message)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    Notification {
+        tags,
+        markdown,
+        priority: notifications.iter().filter_map(|notification| notification.priority).max(),
+        ..Notification::new(format!("{} updates", notifications.len()), message)
+    }
+}
+
+/// Post a notification to an ntfy topic with extra headers (Markdown, Tags, ...), split
+/// into parts that fit, leaving out the parts `delivered` already counts
+async fn post_parts(
+    endpoint: &str,
+    title: &str,
+    message: &str,
+    headers: &[(&str, String)],
+    delivered: &Delivered,
+) -> Result<()> {
+    let client = Client::new();
+    let mut request = client.post(endpoint).header("Title", title);
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+
+    // Long messages go out as numbered continuation parts rather than being cut off
+    for part in split_message(message, NTFY_MAX_BYTES).into_iter().skip(delivered.parts()) {
+        let response = request
+            .try_clone()
+            .ok_or_else(|| Error::notify("notification request can't be cloned"))?
+            .body(part)
+            .send()
+            .await
+            .map_err(Error::notify)?;
+        check_delivery(&response)?;
+        delivered.count_part();
+        say!("✅ Notification sent successfully");
+    }
+    
+    Ok(())
+}
+
+/// Split `message` into parts of at most `limit` bytes, breaking on lines or words
+/// where possible and numbering the parts "(1/3)", "(2/3)", ... when there are several
+pub fn split_message(message: &str, limit: usize) -> Vec<String> {
+    if message.len() <= limit {
+        return vec![message.to_string()];
+    }
+
+    // Leave room for the "\n(12/34)" marker
+    let budget = limit.saturating_sub(10).max(1);
+    let mut parts: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for mut line in message.split('\n') {
+        loop {
+            let needed = if current.is_empty() { line.len() } else { current.len() + 1 + line.len() };
+            if needed <= budget {
+                if !current.is_empty() {
+                    current.push('\n');
+                }
+                current.push_str(line);
+                break;
+            }
+            if !current.is_empty() {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+
+            // A single line longer than the budget: cut at the last space that fits
+            let cut = line.floor_char_boundary(budget);
+            let cut = match line[..cut].rfind(' ') {
+                Some(space) if space > 0 => space,
+                _ if cut == 0 => line.chars().next().map_or(line.len(), char::len_utf8),
+                _ => cut,
+            };
+            parts.push(line[..cut].to_string());
+            line = line[cut..].trim_start();
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    let total = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, part)| format!("{part}\n({}/{total})", index + 1))
+        .collect()
+}
+
+/// Send a notification with a PNG screenshot attached (ntfy attachment upload)
+pub async fn send_notification_with_attachment(
+    endpoint: &str,
+    title: &str,
+    message: &str,
+    png: Vec<u8>,
+    headers: &[(&str, String)],
+) -> Result<()> {
+    let client = Client::new();
+    let mut request = client.put(endpoint)
+        .header("Title", title)
+        .header("Message", message.replace('\n', "\\n"))
+        .header("Filename", "calendar.png");
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let response = request
+        .body(png)
+        .send()
+        .await
+        .map_err(Error::notify)?;
+    check_delivery(&response)?;
+    say!("✅ Notification sent successfully");
+    Ok(())
+}
+
+/// Extra notification line describing a session's membership requirement
+pub fn membership_note(spot: &SpotInfo) -> String {
+    spot.membership
+        .as_ref()
+        .map(|membership| format!("\nRequires: {membership}"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+    use axum::Router;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use std::sync::{Arc, Mutex};
+    use crate::config::Config;
+    use crate::parse::tests::spot;
+
+    // A backend answering each request with the next scripted status (then 200 OK),
+    // recording every body it was sent
+    async fn backend(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<(u16, String)>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().fallback(post({
+            let requests = requests.clone();
+            move |body: String| async move {
+                let mut requests = requests.lock().unwrap();
+                let status = statuses.get(requests.len()).copied().unwrap_or(200);
+                requests.push((status, body));
+                StatusCode::from_u16(status).unwrap()
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn backend_errors_are_retried_or_reported() {
+        let (url, requests) = backend(vec![503]).await;
+        let webhook: NotifierConfig = toml::from_str(&format!("type = \"webhook\"\nurl = \"{url}\"")).unwrap();
+        send_with_retry(&webhook, &Notification::new("Spot change", "2 spots left")).await.unwrap();
+        let statuses: Vec<u16> = requests.lock().unwrap().iter().map(|(status, _)| *status).collect();
+        assert_eq!(statuses, [503, 200]);
+
+        // A rejection won't go through on a second try either
+        let (url, requests) = backend(vec![400]).await;
+        let webhook: NotifierConfig = toml::from_str(&format!("type = \"webhook\"\nurl = \"{url}\"")).unwrap();
+        let error = send_with_retry(&webhook, &Notification::new("Spot change", "2 spots left")).await.unwrap_err();
+        assert!(matches!(error, Error::Status { status, .. } if status == 400));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn long_ntfy_messages_resume_at_the_failed_part() {
+        let (url, requests) = backend(vec![200, 502]).await;
+        let ntfy: NotifierConfig = toml::from_str(&format!("type = \"ntfy\"\nendpoint = \"{url}\"")).unwrap();
+        let message = ["a".repeat(3000), "b".repeat(3000), "c".repeat(3000)].join("\n");
+        send_with_retry(&ntfy, &Notification::new("Spot change", message)).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let sent: Vec<(u16, char)> =
+            requests.iter().map(|(status, body)| (*status, body.chars().next().unwrap())).collect();
+        assert_eq!(sent, [(200, 'a'), (502, 'b'), (200, 'b'), (200, 'c')]);
+    }
+
+    #[tokio::test]
+    async fn long_signal_messages_resume_at_the_failed_part() {
+        let (url, requests) = backend(vec![503]).await;
+        let signal: NotifierConfig = toml::from_str(&format!(
+            "type = \"signal\"\napi_url = \"{url}\"\nnumber = \"+15195550100\"\nrecipients = [\"+15195550123\"]"
+        )).unwrap();
+        let message = ["a".repeat(1500), "b".repeat(1500)].join("\n");
+        send_with_retry(&signal, &Notification::new("Spot change", message)).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let sent: Vec<(u16, String)> = requests
+            .iter()
+            .map(|(status, body)| {
+                let body: serde_json::Value = serde_json::from_str(body).unwrap();
+                (*status, body["message"].as_str().unwrap()[..13].to_string())
+            })
+            .collect();
+        assert_eq!(sent, [(503, "Spot change\na".into()), (200, "Spot change\na".into()), (200, "b".repeat(13))]);
+    }
+
+    #[tokio::test]
+    async fn teams_cards_lay_out_the_session_or_the_message() {
+        let (url, requests) = backend(vec![]).await;
+        let teams: NotifierConfig = toml::from_str(&format!("type = \"teams\"\nwebhook_url = \"{url}\"")).unwrap();
+        let change = Notification {
+            spot: Some(spot("Beginner", "2 spots left")),
+            previous_spots: Some("Full".into()),
+            ..Notification::new("Spot change: Drop-in", "Beginner (Drop-in) on 2025-03-14 @ 19:00:00: Full → 2 spots left")
+        };
+        teams.send(&change).await.unwrap();
+        teams.send(&Notification::new("3 updates", "Spot change: Drop-in")).await.unwrap();
+
+        let cards: Vec<serde_json::Value> =
+            requests.lock().unwrap().iter().map(|(_, body)| serde_json::from_str(body).unwrap()).collect();
+        let details = |card: &serde_json::Value| card["attachments"][0]["content"]["body"][1].clone();
+        assert_eq!(details(&cards[0])["facts"][4]["value"], "Full → 2 spots left");
+        assert_eq!(details(&cards[1])["text"], "Spot change: Drop\\-in");
+    }
+
+    #[test]
+    fn calendar_events_are_written_in_utc() {
+        let mut spot = spot("Tennis", "3 spots");
+        let event = render_event("uid", &spot, "Tennis");
+        assert!(event.contains("DTSTART:20250314T230000Z\r\n"), "{event}");
+        assert!(event.contains("DTEND:20250315T003000Z\r\n"), "{event}");
+        assert!(!event.contains("TZID"));
+
+        // Before the DST change Toronto is five hours behind
+        spot.date = "2025-01-10".into();
+        let event = render_event("uid", &spot, "Tennis");
+        assert!(event.contains("DTSTART:20250111T000000Z\r\n"), "{event}");
+    }
+
+    #[test]
+    fn markdown_messages_escape_adversarial_names() {
+        let prev = spot("Beginner", "Full");
+        let mut current = spot("Beginner", "1 spot left");
+        current.product_name = "**FREE** [claim](https://evil.example) <img src=x>".into();
+
+        let message = format_change(&prev, &current, true);
+        assert!(message.contains(r"\*\*FREE\*\* \[claim\]\(https://evil\.example\) \<img src=x\>"));
+        assert!(message.contains("~~Full~~ → **1 spot left** ⬆️"));
+
+        // Plain-text backends get the name untouched
+        assert!(format_change(&prev, &current, false).contains("**FREE** [claim]"));
+    }
+
+    #[test]
+    fn markdown_escapes_spot_and_membership_text() {
+        let prev = spot("Beginner", "Full");
+        let mut current = spot("Beginner", "~~2~~ spots left");
+        current.membership = Some("Student_Pass`".into());
+
+        let message = format_change(&prev, &current, true);
+        assert!(message.contains(r"**\~\~2\~\~ spots left**"));
+        assert!(message.ends_with(r"Requires: Student\_Pass\`"));
+    }
+
+    #[test]
+    fn short_messages_are_not_split() {
+        assert_eq!(split_message("2 spots left", 4096), vec!["2 spots left"]);
+    }
+
+    #[test]
+    fn long_messages_split_on_lines_with_markers() {
+        let message = ["a".repeat(30), "b".repeat(30), "c".repeat(30)].join("\n");
+        let parts = split_message(&message, 50);
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], format!("{}\n(1/3)", "a".repeat(30)));
+        assert_eq!(parts[2], format!("{}\n(3/3)", "c".repeat(30)));
+        assert!(parts.iter().all(|part| part.len() <= 50));
+    }
+
+    #[test]
+    fn long_lines_split_on_words_and_char_boundaries() {
+        let parts = split_message(&"🎾 court ".repeat(20), 40);
+        assert!(parts.iter().all(|part| part.len() <= 40));
+        let rejoined: String = parts
+            .iter()
+            .map(|part| part.rsplit_once('\n').unwrap().0)
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(rejoined.split_whitespace().count(), 40);
+    }
+
+    #[test]
+    fn ntfy_auth_uses_a_token_or_basic_credentials() {
+        let config: Config = toml::from_str(r#"
+            interval_seconds = 60
+            ntfy_endpoint = "https://ntfy.example.com/topic"
+            ntfy_username = "user"
+            ntfy_password = "pass"
+            program_ids = []
+
+            [[notifiers]]
+            type = "ntfy"
+            endpoint = "https://ntfy.example.com/other"
+            token = { env = "UWTENNIS_UNSET_NTFY_TOKEN" }
+        "#).unwrap();
+        let notifiers = config.notifiers();
+        let [NotifierConfig::Ntfy(basic), NotifierConfig::Ntfy(token)] =
+            [&notifiers[0].notifier, &notifiers[1].notifier]
+        else {
+            panic!("expected two ntfy notifiers");
+        };
+
+        assert_eq!(basic.authorization().unwrap().as_deref(), Some("Basic dXNlcjpwYXNz"));
+        assert!(matches!(token.authorization(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn digests_combine_a_cycle_into_one_notification() {
+        let change = |title: &str, tag: &str, priority| Notification {
+            tags: vec![tag.to_string()],
+            priority: Some(priority),
+            ..Notification::new(title, "Full → 1 spot left")
+        };
+        let single = summarize(vec![change("Spot change: Drop-in", "🎾", Priority::High)]);
+        assert_eq!(single.title, "Spot change: Drop-in");
+
+        let digest = summarize(vec![
+            change("Spot change: Drop-in", "🎾", Priority::High),
+            change("Spot change: Ladder", "🎾", Priority::Low),
+            change("Spot change: Badminton", "🏸", Priority::Default),
+        ]);
+        assert_eq!(digest.title, "3 updates");
+        assert_eq!(digest.tags, ["🎾", "🏸"]);
+        assert_eq!(digest.priority, Some(Priority::High));
+        assert!(digest.message.starts_with("Spot change: Drop-in\nFull → 1 spot left\n\nSpot change: Ladder\n"));
+    }
+
+    #[tokio::test]
+    async fn quiet_hours_hold_notifications_for_a_morning_digest() {
+        let config: Config = toml::from_str(r#"
+            quiet_hours = "23:00-07:00"
+            program_ids = []
+
+            [[notifiers]]
+            type = "debug"
+            buffer = "overnight"
+        "#).unwrap();
+        let window = config.quiet_hours.unwrap();
+        assert!(window.contains(NaiveTime::from_hms_opt(2, 0, 0).unwrap()));
+        assert!(!window.contains(NaiveTime::from_hms_opt(7, 0, 0).unwrap()));
+        assert!(config.quiet_hours_digest);
+
+        let change = |spots: &str, session: SpotInfo| Notification {
+            spot: Some(session),
+            ..Notification::new("Spot change: Drop-in", spots)
+        };
+        let mut later = spot("Beginner", "2 spots left");
+        later.time = "21:00:00".into();
+        let mut overnight = Digest::overnight();
+        let notifiers = config.notifiers();
+        notify_or_hold(&notifiers, &change("Full → 1 spot left", spot("Beginner", "1 spot left")), &mut overnight).await;
+        notify_or_hold(&notifiers, &change("1 spot left → Full", spot("Beginner", "Full")), &mut overnight).await;
+        notify_or_hold(&notifiers, &change("Full → 2 spots left", later), &mut overnight).await;
+        assert!(crate::debug::recorded("overnight").is_empty());
+
+        // Only where each session ended up is sent, in one notification
+        assert_eq!(overnight.len(), 2);
+        overnight.flush().await;
+        let sent = crate::debug::recorded("overnight");
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].title, "2 updates");
+        assert!(sent[0].message.contains("1 spot left → Full") && !sent[0].message.contains("Full → 1 spot left"));
+        assert!(overnight.is_empty());
+    }
+
+    #[test]
+    fn ntfy_headers_link_to_registration_by_urgency() {
+        let opened = Notification {
+            spot: Some(spot("Beginner", "1 spot left")),
+            priority: Some(Priority::for_change("Full", "1 spot left")),
+            ..Notification::new("Spot change", "")
+        };
+        let url = crate::fetch::registration_url("program");
+        assert_eq!(opened.ntfy_headers(), [
+            ("Priority", "high".to_string()),
+            ("Actions", format!("view, Register, {url}, clear=true")),
+            ("Click", url.clone()),
+        ]);
+
+        let filled = Notification {
+            spot: Some(spot("Beginner", "Full")),
+            priority: Some(Priority::for_change("1 spot left", "Full")),
+            ..Notification::new("Spot change", "")
+        };
+        assert_eq!(filled.ntfy_headers(), [("Priority", "low".to_string()), ("Click", url)]);
+    }
+
+    #[test]
+    fn plain_text_spells_out_symbols_and_fields() {
+        assert_eq!(
+            crate::style::plain_text("🔄 Change detected: 🎾 Beginner (Drop-in) on 2025-03-14 @ 19:00:00 - Full → 2 spots left ⬆️\n🚲 ~12 min by bike"),
+            "Change detected: Beginner (Drop-in) on 2025-03-14 at 19:00:00 - Full to 2 spots left (up)\nabout 12 min by bike"
+        );
+        assert_eq!(crate::style::plain_text("Café Ω"), "Café Ω");
+
+        let mut current = spot("Beginner", "2 spots left");
+        current.membership = Some("Student Pass".into());
+        assert_eq!(
+            format_change_plain(&spot("Beginner", "Full"), &current),
+            "Program: Beginner. Session: Drop-in. Date: Friday, March 14, 2025. Time: 7:00 PM to 8:30 PM. \
+             Location: PAC. Spots: was Full, now 2 spots left. Requires: Student Pass."
+        );
+    }
+}
@@ -0,0 +1,1029 @@
+//! Scraping the program calendar and instance pages
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+
+use crate::clock::FACILITY_TZ;
+use crate::error::{Error, Result};
+
+// One session in a program calendar's ApptInfo. Only the ID and times are needed;
+// everything is read leniently (numbers or strings, camelCase or PascalCase) so
+// small changes on the site don't break whole calendars, and fields nobody knows
+// about end up in `unrecognized`
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Appointment {
+    #[serde(rename = "ID", alias = "id", alias = "Id", default, deserialize_with = "text")]
+    pub id: String,
+
+    #[serde(rename = "StartDate", alias = "startDate", default, deserialize_with = "text")]
+    pub start_date: String,
+
+    #[serde(rename = "EndDate", alias = "endDate", default, deserialize_with = "text")]
+    pub end_date: String,
+
+    #[serde(rename = "Location", alias = "location", default, deserialize_with = "text")]
+    pub location: String,
+
+    #[serde(rename = "ProductName", alias = "productName", default, deserialize_with = "text")]
+    pub product_name: String,
+
+    // Link to this session's own page, when the calendar includes one
+    #[serde(rename = "PortalURL", alias = "portalUrl", alias = "portalURL", default, deserialize_with = "lenient")]
+    pub portal_url: Option<String>,
+
+    #[serde(rename = "ProductId", alias = "productId", default, deserialize_with = "lenient")]
+    pub product_id: Option<String>,
+
+    #[serde(rename = "ProgramInstanceId", alias = "programInstanceId", default, deserialize_with = "lenient")]
+    pub program_instance_id: Option<String>,
+
+    #[serde(rename = "ProgramDescription", alias = "programDescription", default, deserialize_with = "lenient")]
+    pub program_description: Option<String>,
+
+    #[serde(rename = "Subject", alias = "subject", default, deserialize_with = "lenient")]
+    pub subject: Option<String>,
+
+    // Spots in the session, and how many are taken or waiting
+    #[serde(rename = "ClassSize", alias = "classSize", default, deserialize_with = "lenient")]
+    pub class_size: Option<u32>,
+
+    #[serde(rename = "NumberRegistered", alias = "numberRegistered", default, deserialize_with = "lenient")]
+    pub number_registered: Option<u32>,
+
+    #[serde(rename = "NumberOnWaitlist", alias = "numberOnWaitlist", default, deserialize_with = "lenient")]
+    pub number_on_waitlist: Option<u32>,
+
+    #[serde(rename = "InstructorId", alias = "instructorId", default, deserialize_with = "lenient")]
+    pub instructor_id: Option<String>,
+
+    // e.g. "Jane D."
+    #[serde(
+        rename = "InstructorFirstNameLastInitial",
+        alias = "instructorFirstNameLastInitial",
+        default,
+        deserialize_with = "lenient"
+    )]
+    pub instructor_name: Option<String>,
+
+    #[serde(rename = "IsInstructor", alias = "isInstructor", default, deserialize_with = "lenient")]
+    pub is_instructor: Option<bool>,
+
+    #[serde(rename = "IsRecurring", alias = "isRecurring", default, deserialize_with = "lenient")]
+    pub is_recurring: Option<bool>,
+
+    #[serde(rename = "RecurrenceInfo", alias = "recurrenceInfo", default, deserialize_with = "lenient")]
+    pub recurrence_info: Option<String>,
+
+    #[serde(rename = "AppointmentType", alias = "appointmentType", default, deserialize_with = "lenient")]
+    pub appointment_type: Option<i64>,
+
+    #[serde(rename = "Status", alias = "status", default, deserialize_with = "lenient")]
+    pub status: Option<i64>,
+
+    #[serde(rename = "AllDay", alias = "allDay", default, deserialize_with = "lenient")]
+    pub all_day: Option<bool>,
+
+    #[serde(rename = "ResourceId", alias = "resourceId", default, deserialize_with = "lenient")]
+    pub resource_id: Option<String>,
+
+    // Anything else the site sent, kept so schema changes can be reported
+    #[serde(flatten)]
+    pub unrecognized: BTreeMap<String, serde_json::Value>,
+}
+
+/// A JSON value as text: strings as they are, numbers and booleans spelled out,
+/// and nothing for null, arrays or objects
+fn value_text(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(text) => Some(text),
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        serde_json::Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// Deserialize a required-looking field as text, whatever JSON type it came as
+fn text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+    Ok(value_text(serde_json::Value::deserialize(deserializer)?).unwrap_or_default())
+}
+
+/// Deserialize an optional field from whatever JSON type it came as ("12" or 12,
+/// "True" or true), leaving it out when it can't be read
+fn lenient<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+{
+    let text = value_text(serde_json::Value::deserialize(deserializer)?);
+    Ok(text.and_then(|text| text.parse().ok().or_else(|| text.trim().to_ascii_lowercase().parse().ok())))
+}
+
+// Ways a calendar's ApptInfo strayed from the schema this was written against
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SchemaDrift {
+    // Field names no Appointment field knows, across every appointment
+    pub unrecognized: BTreeSet<String>,
+    // Fields the monitor relies on that came back empty or missing
+    pub missing: BTreeSet<&'static str>,
+}
+
+impl SchemaDrift {
+    pub fn of(appts: &[Appointment]) -> Self {
+        let mut drift = SchemaDrift::default();
+        for appt in appts {
+            drift.unrecognized.extend(appt.unrecognized.keys().cloned());
+            for (name, value) in [("ID", &appt.id), ("StartDate", &appt.start_date), ("EndDate", &appt.end_date)] {
+                if value.trim().is_empty() {
+                    drift.missing.insert(name);
+                }
+            }
+        }
+        drift
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unrecognized.is_empty() && self.missing.is_empty()
+    }
+
+    /// e.g. "unrecognized fields: Capacity, Coach; missing fields: EndDate"
+    pub fn describe(&self) -> String {
+        let join = |names: Vec<&str>| names.join(", ");
+        let mut parts = Vec::new();
+        if !self.unrecognized.is_empty() {
+            parts.push(format!("unrecognized fields: {}", join(self.unrecognized.iter().map(String::as_str).collect())));
+        }
+        if !self.missing.is_empty() {
+            parts.push(format!("missing fields: {}", join(self.missing.iter().copied().collect())));
+        }
+        parts.join("; ")
+    }
+}
+
+impl Appointment {
+    /// The optional fields the calendar gave, as form fields named like the
+    /// site's, for posting the appointment back
+    pub fn known_fields(&self) -> Vec<(&'static str, String)> {
+        let text = |name, value: &Option<String>| value.clone().map(|value| (name, value));
+        let show = |name, value: Option<String>| value.map(|value| (name, value));
+        [
+            text("PortalURL", &self.portal_url),
+            text("ProductId", &self.product_id),
+            text("ProgramInstanceId", &self.program_instance_id),
+            text("ProgramDescription", &self.program_description),
+            text("Subject", &self.subject),
+            show("ClassSize", self.class_size.map(|n| n.to_string())),
+            show("NumberRegistered", self.number_registered.map(|n| n.to_string())),
+            show("NumberOnWaitlist", self.number_on_waitlist.map(|n| n.to_string())),
+            text("InstructorId", &self.instructor_id),
+            text("InstructorFirstNameLastInitial", &self.instructor_name),
+            show("IsInstructor", self.is_instructor.map(|b| b.to_string())),
+            show("IsRecurring", self.is_recurring.map(|b| b.to_string())),
+            text("RecurrenceInfo", &self.recurrence_info),
+            show("AppointmentType", self.appointment_type.map(|n| n.to_string())),
+            show("Status", self.status.map(|n| n.to_string())),
+            show("AllDay", self.all_day.map(|b| b.to_string())),
+            text("ResourceId", &self.resource_id),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Open spots, capacity and waitlist from the calendar's own counts, when it has them
+    pub fn availability(&self) -> Option<Availability> {
+        let capacity = self.class_size?;
+        let registered = self.number_registered?;
+        Some(Availability {
+            open: capacity.saturating_sub(registered),
+            capacity: Some(capacity),
+            waitlist: self.number_on_waitlist,
+        })
+    }
+}
+
+// Badge variants rendered in a session's .spots-tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpotBadge {
+    SpotsLeft(u32),
+    Register,
+    Waitlist,
+    Full,
+    Unknown,
+}
+
+impl SpotBadge {
+    /// Number of open spots, when the badge makes that clear
+    pub fn open_count(self) -> Option<u32> {
+        match self {
+            SpotBadge::SpotsLeft(count) => Some(count),
+            SpotBadge::Waitlist | SpotBadge::Full => Some(0),
+            SpotBadge::Register | SpotBadge::Unknown => None,
+        }
+    }
+
+    /// Whether the badge means a spot can be registered for right now
+    pub fn is_open(self) -> bool {
+        match self {
+            SpotBadge::SpotsLeft(count) => count > 0,
+            SpotBadge::Register => true,
+            SpotBadge::Waitlist | SpotBadge::Full | SpotBadge::Unknown => false,
+        }
+    }
+}
+
+// The numbers behind a spots badge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Availability {
+    pub open: u32,
+    // Only shown by badges like "3 of 12 spots left"
+    pub capacity: Option<u32>,
+    // People already waiting, for badges like "Waitlist (4)"
+    pub waitlist: Option<u32>,
+}
+
+impl Availability {
+    /// Numbers in a normalized spots text; `None` when it doesn't give a count ("Register")
+    pub fn parse(text: &str) -> Option<Self> {
+        let numbers: Vec<u32> = ascii_digits(text)
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|number| number.parse().ok())
+            .collect();
+        match classify_spots(text) {
+            SpotBadge::SpotsLeft(open) => Some(Availability { open, capacity: numbers.get(1).copied(), waitlist: None }),
+            SpotBadge::Full | SpotBadge::Waitlist => {
+                Some(Availability { open: 0, capacity: None, waitlist: numbers.first().copied() })
+            }
+            SpotBadge::Register | SpotBadge::Unknown => None,
+        }
+    }
+}
+
+/// Whether availability went up, down or sideways between two spots texts: by open-spot
+/// count when both badges give one, otherwise by whether registration is open at all
+pub fn compare_availability(previous: &str, current: &str) -> std::cmp::Ordering {
+    match (Availability::parse(previous), Availability::parse(current)) {
+        (Some(previous), Some(current)) => current.open.cmp(&previous.open),
+        _ => classify_spots(current).is_open().cmp(&classify_spots(previous).is_open()),
+    }
+}
+
+// Badge classifiers, tried in order; add new variants here as the site introduces them
+const BADGE_CLASSIFIERS: &[fn(&str) -> Option<SpotBadge>] = &[
+    classify_configured,
+    classify_spots_left,
+    classify_keyword,
+    classify_count,
+];
+
+// Extra badge patterns from the config's `spot_patterns`, set once at startup
+static SPOT_PATTERNS: RwLock<Vec<Regex>> = RwLock::new(Vec::new());
+
+pub fn set_spot_patterns(patterns: Vec<Regex>) {
+    *SPOT_PATTERNS.write().unwrap_or_else(|e| e.into_inner()) = patterns;
+}
+
+// Number of badges seen that no classifier recognized
+static UNKNOWN_BADGES: AtomicU64 = AtomicU64::new(0);
+
+/// How many badges no classifier has recognized since startup
+pub fn unknown_badges() -> u64 {
+    UNKNOWN_BADGES.load(Ordering::Relaxed)
+}
+
+// Information about a specific appointment spot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotInfo {
+    // Secondary identity that survives appointment ID regeneration
+    pub identity: String,
+    // Repeated across many sessions, so these share interned allocations
+    pub program_id: Arc<str>,
+    pub program_name: Arc<str>,
+    pub product_name: Arc<str>,
+    pub location: Arc<str>,
+    pub date: Arc<str>,
+    pub time: Arc<str>,
+    pub end_time: Arc<str>,
+    pub spots: String,
+    pub price: Option<String>,
+    pub membership: Option<Arc<str>>,
+    // When the spots text was read off the site, in facility time
+    #[serde(default)]
+    pub observed_at: Option<NaiveDateTime>,
+    // Not refreshed within `stale_after_seconds`, e.g. after repeated failed checks
+    #[serde(default)]
+    pub stale: bool,
+    // The session's own page from its PortalURL, absolute or relative to the site
+    #[serde(default)]
+    pub portal_url: Option<String>,
+    // Other programs listing the same session, as (ID, name)
+    #[serde(default)]
+    pub also_listed_under: Vec<(Arc<str>, Arc<str>)>,
+}
+
+// Pool of shared strings so identical names are only allocated once; entries
+// stay until `prune_interned` finds nothing else holding them
+static INTERNER: LazyLock<Mutex<HashSet<Arc<str>>>> = LazyLock::new(Default::default);
+
+/// Return the shared copy of `value`, adding it to the pool if needed
+pub fn intern(value: &str) -> Arc<str> {
+    let mut pool = INTERNER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(existing) = pool.get(value) {
+        return existing.clone();
+    }
+    let shared: Arc<str> = Arc::from(value);
+    pool.insert(shared.clone());
+    shared
+}
+
+/// Drop the pooled strings nothing outside the pool uses any more
+pub fn prune_interned() {
+    let mut pool = INTERNER.lock().unwrap_or_else(|e| e.into_inner());
+    pool.retain(|shared| Arc::strong_count(shared) > 1);
+}
+
+impl SpotInfo {
+    /// When the session starts, in facility time
+    pub fn start(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(&format!("{} {}", self.date, self.time), "%Y-%m-%d %H:%M:%S").ok()
+    }
+
+    /// Whether the spots text was last read more than `max_age` before `now`;
+    /// sessions with no observation time count as stale
+    pub fn is_stale(&self, now: NaiveDateTime, max_age: chrono::Duration) -> bool {
+        self.observed_at.is_none_or(|observed_at| now - observed_at > max_age)
+    }
+
+    /// " (stale, last seen 2025-03-14 18:05)" for stale sessions, otherwise nothing
+    pub fn staleness_note(&self) -> String {
+        match (self.stale, self.observed_at) {
+            (false, _) => String::new(),
+            (true, Some(observed_at)) => format!(" (stale, last seen {})", observed_at.format("%Y-%m-%d %H:%M")),
+            (true, None) => " (stale)".into(),
+        }
+    }
+
+    /// Where to register: the session's own page when the site gave one, otherwise
+    /// the program's registration page
+    pub fn url(&self) -> String {
+        match self.portal_url.as_deref().map(str::trim).filter(|url| !url.is_empty()) {
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => url.to_string(),
+            Some(url) => format!("{}/{}", crate::fetch::SITE_URL.trim_end_matches('/'), url.trim_start_matches('/')),
+            None => crate::fetch::registration_url(&self.program_id),
+        }
+    }
+
+    /// Whether `other` is the same physical session, whatever its spots
+    pub fn same_session(&self, other: &SpotInfo) -> bool {
+        (&self.program_id, &self.date, &self.time, &self.location, &self.product_name)
+            == (&other.program_id, &other.date, &other.time, &other.location, &other.product_name)
+    }
+
+    /// IDs of every program listing the session, its own first
+    pub fn program_ids(&self) -> impl Iterator<Item = &Arc<str>> {
+        std::iter::once(&self.program_id).chain(self.also_listed_under.iter().map(|(id, _)| id))
+    }
+
+    /// Whether `program` is the name or ID of any program listing the session
+    pub fn listed_under(&self, program: &str) -> bool {
+        std::iter::once((&self.program_id, &self.program_name))
+            .chain(self.also_listed_under.iter().map(|(id, name)| (id, name)))
+            .any(|(id, name)| program.eq_ignore_ascii_case(id) || program.eq_ignore_ascii_case(name))
+    }
+
+    /// Names of every program listing the session, e.g. "Beginner + Intermediate"
+    pub fn programs_label(&self) -> String {
+        std::iter::once(&*self.program_name)
+            .chain(self.also_listed_under.iter().map(|(_, name)| &**name))
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    /// Open spots, capacity and waitlist, when the badge gives numbers
+    pub fn availability(&self) -> Option<Availability> {
+        Availability::parse(&self.spots)
+    }
+
+    /// Swap the repeated strings for their shared copies, e.g. after loading from disk
+    pub fn interned(self) -> Self {
+        SpotInfo {
+            program_id: intern(&self.program_id),
+            program_name: intern(&self.program_name),
+            product_name: intern(&self.product_name),
+            location: intern(&self.location),
+            date: intern(&self.date),
+            time: intern(&self.time),
+            end_time: intern(&self.end_time),
+            membership: self.membership.as_deref().map(intern),
+            also_listed_under: self.also_listed_under.iter().map(|(id, name)| (intern(id), intern(name))).collect(),
+            ..self
+        }
+    }
+}
+
+// Details scraped from a session's instance card
+#[derive(Debug, Clone)]
+pub struct InstanceDetails {
+    pub spots: String,
+    pub price: Option<String>,
+    pub membership: Option<String>,
+}
+
+/// Interpret a site timestamp as a wall-clock time at the facility.
+/// Timestamps with an offset are converted; naive ones are already local to it.
+pub fn facility_datetime(timestamp: &str) -> Option<NaiveDateTime> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(dt.with_timezone(&FACILITY_TZ).naive_local());
+    }
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(timestamp.get(..10)?, "%Y-%m-%d").ok()?;
+            Some(date.and_time(NaiveTime::MIN))
+        })
+}
+
+/// Normalize scraped spot text so cosmetic markup changes don't read as real changes
+pub fn normalize_spots(raw: &str) -> String {
+    // Entities that survive the HTML parser when the site double-encodes them
+    let decoded = raw
+        .replace("&nbsp;", " ")
+        .replace("&#160;", " ")
+        .replace("&#39;", "'")
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .replace('\u{a0}', " ");
+
+    sanitize_text(&decoded).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Classify normalized spot text into a known badge variant
+pub fn classify_spots(text: &str) -> SpotBadge {
+    let lower = ascii_digits(text).to_lowercase();
+    BADGE_CLASSIFIERS
+        .iter()
+        .find_map(|classify| classify(&lower))
+        .unwrap_or(SpotBadge::Unknown)
+}
+
+/// "1 spot left", "3 spots left", "3 of 12 spots left"
+fn classify_spots_left(text: &str) -> Option<SpotBadge> {
+    let count = text
+        .strip_suffix("spot left")
+        .or_else(|| text.strip_suffix("spots left"))
+        .or_else(|| text.strip_suffix("spot(s) left"))?;
+    let (open, _capacity) = count.split_once(" of ").or_else(|| count.split_once('/')).unwrap_or((count, ""));
+    open.trim().parse().ok().map(SpotBadge::SpotsLeft)
+}
+
+/// Configured patterns, whose `open` group holds the number of open spots
+fn classify_configured(text: &str) -> Option<SpotBadge> {
+    classify_with(text, &SPOT_PATTERNS.read().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// The open spots held by the `open` group of the first of `patterns` that matches
+fn classify_with(text: &str, patterns: &[Regex]) -> Option<SpotBadge> {
+    patterns
+        .iter()
+        .find_map(|pattern| pattern.captures(text)?.name("open")?.as_str().trim().parse().ok())
+        .map(SpotBadge::SpotsLeft)
+}
+
+// Words a bare count of open spots may come with, before or after the number
+const COUNT_WORDS: &[&str] = &[
+    "spot", "spots", "spot(s)", "place", "places", "opening", "openings",
+    "left", "available", "remaining", "open", ":", "-",
+];
+
+/// Counts without the usual "spots left": "12 spots", "Spots: 3", "Available - 2", "3"
+fn classify_count(text: &str) -> Option<SpotBadge> {
+    let spaced = text.replace(':', " : ");
+    let mut count = None;
+    for word in spaced.split_whitespace() {
+        match word.parse::<u32>() {
+            Ok(number) if count.is_none() => count = Some(number),
+            Err(_) if COUNT_WORDS.contains(&word) => {}
+            _ => return None,
+        }
+    }
+    count.map(SpotBadge::SpotsLeft)
+}
+
+/// Swap digits from other scripts (fullwidth, Arabic-Indic, Devanagari, ...) for
+/// ASCII ones so counts parse the same whatever the page's locale
+pub fn ascii_digits(text: &str) -> String {
+    // First code point of each block of ten decimal digits
+    const ZEROS: &[u32] = &[
+        0x0660, 0x06f0, 0x07c0, 0x0966, 0x09e6, 0x0a66, 0x0ae6, 0x0b66, 0x0be6,
+        0x0c66, 0x0ce6, 0x0d66, 0x0e50, 0x0ed0, 0x0f20, 0x1040, 0xff10,
+    ];
+    text.chars()
+        .map(|c| {
+            ZEROS
+                .iter()
+                .find(|&&zero| (zero..zero + 10).contains(&(c as u32)))
+                .and_then(|&zero| char::from_digit(c as u32 - zero, 10))
+                .unwrap_or(c)
+        })
+        .collect()
+}
+
+/// Single-word badges shown in place of a count, possibly with a waitlist
+/// length: "Waitlist (3)", "3 on waitlist"
+fn classify_keyword(text: &str) -> Option<SpotBadge> {
+    let word = text.split('(').next().unwrap_or(text).trim();
+    match word {
+        "register" => Some(SpotBadge::Register),
+        "waitlist" | "join waitlist" => Some(SpotBadge::Waitlist),
+        _ if word.ends_with(" on waitlist") => Some(SpotBadge::Waitlist),
+        "full" => Some(SpotBadge::Full),
+        _ => None,
+    }
+}
+
+/// Drop control and bidirectional-override characters from scraped text, so it
+/// can't break headers, fake line breaks, or visually reorder a message
+pub fn sanitize_text(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            c if c.is_whitespace() && c.is_control() => Some(' '),
+            c if c.is_control() => None,
+            '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+// A program's calendar with its two sources, the hdnDates list and the ApptInfo
+// appointments, matched up
+#[derive(Debug, Default)]
+pub struct Calendar<'a> {
+    // Every date with an appointment, listed or not, with the appointments starting on it
+    pub days: BTreeMap<NaiveDate, Vec<(&'a Appointment, NaiveDateTime)>>,
+    // Listed in hdnDates without any appointment starting on them
+    pub empty_dates: Vec<NaiveDate>,
+    // Appointments starting on dates hdnDates doesn't list; still checked
+    pub unlisted: Vec<&'a Appointment>,
+    // hdnDates entries and appointment start dates that couldn't be read at all
+    pub unparseable: Vec<String>,
+}
+
+impl Calendar<'_> {
+    pub fn is_consistent(&self) -> bool {
+        self.empty_dates.is_empty() && self.unlisted.is_empty() && self.unparseable.is_empty()
+    }
+
+    /// e.g. "listed dates without sessions: 2025-03-14; sessions on unlisted dates: abc"
+    pub fn describe(&self) -> String {
+        let empty_dates: Vec<String> = self.empty_dates.iter().map(NaiveDate::to_string).collect();
+        let unlisted: Vec<String> = self.unlisted.iter().map(|appt| appt.id.clone()).collect();
+        [
+            ("listed dates without sessions", empty_dates),
+            ("sessions on unlisted dates", unlisted),
+            ("unreadable dates", self.unparseable.clone()),
+        ]
+        .into_iter()
+        .filter(|(_, items)| !items.is_empty())
+        .map(|(label, items)| format!("{label}: {}", items.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
+    }
+}
+
+/// Match the dates in hdnDates with the appointments in ApptInfo, keeping whatever only
+/// one of them has instead of silently dropping it
+pub fn reconcile<'a>(appts: &'a [Appointment], dates: &[String]) -> Calendar<'a> {
+    let mut calendar = Calendar::default();
+    let mut listed = HashSet::new();
+    for date in dates {
+        match facility_datetime(date) {
+            Some(date) => {
+                listed.insert(date.date());
+            }
+            None => calendar.unparseable.push(date.clone()),
+        }
+    }
+
+    for appt in appts {
+        let Some(start) = facility_datetime(&appt.start_date) else {
+            calendar.unparseable.push(format!("{} (session {})", appt.start_date, appt.id));
+            continue;
+        };
+        if !listed.contains(&start.date()) {
+            calendar.unlisted.push(appt);
+        }
+        calendar.days.entry(start.date()).or_default().push((appt, start));
+    }
+
+    calendar.empty_dates = listed.into_iter().filter(|date| !calendar.days.contains_key(date)).collect();
+    calendar.empty_dates.sort();
+    calendar
+}
+
+/// Extract appointments and dates from a program calendar page
+pub fn parse_initial(html: &str) -> Result<(Vec<Appointment>, Vec<String>)> {
+    let document = Html::parse_document(html);
+
+    // Extract and parse appointments JSON
+    let appt_sel = Selector::parse("input#ApptInfo").unwrap();
+    let raw_appts = document
+        .select(&appt_sel)
+        .next()
+        .and_then(|e| e.value().attr("value"))
+        .ok_or_else(|| Error::Parse("missing #ApptInfo input".into()))?;
+    let appts: Vec<Appointment> = serde_json::from_str(raw_appts)?;
+
+    // Extract and parse dates JSON
+    let dates_sel = Selector::parse("input#hdnDates").unwrap();
+    let raw_dates = document
+        .select(&dates_sel)
+        .next()
+        .and_then(|e| e.value().attr("value"))
+        .ok_or_else(|| Error::Parse("missing #hdnDates input".into()))?;
+    let dates: Vec<String> = serde_json::from_str(raw_dates)?;
+
+    Ok((appts, dates))
+}
+
+/// Appointment IDs of every instance card in a FilterProgramInstances response
+pub fn instance_appointment_ids(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let instance_sel = Selector::parse("div[data-instance-appointmentid]").unwrap();
+    document
+        .select(&instance_sel)
+        .filter_map(|el| el.value().attr("data-instance-appointmentid"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Extract spots, price and membership from the instance card of one appointment
+pub fn parse_instance(html: &str, appointment_id: &str) -> InstanceDetails {
+    let document = Html::parse_document(html);
+    // Matched by hand rather than in the selector, so odd IDs can't make it invalid
+    let instance_sel = Selector::parse("div[data-instance-appointmentid]").unwrap();
+    let spot_sel = Selector::parse(".spots-tag").unwrap();
+    let price_sel = Selector::parse(".price, .instance-price").unwrap();
+    let membership_sel = Selector::parse(".membership, .membership-required, .requirement").unwrap();
+
+    let Some(instance) = document
+        .select(&instance_sel)
+        .find(|el| el.value().attr("data-instance-appointmentid") == Some(appointment_id))
+    else {
+        return InstanceDetails { spots: "N/A".into(), price: None, membership: None };
+    };
+
+    let spots = match instance.select(&spot_sel).next() {
+        Some(el) => {
+            let spots = normalize_spots(&el.text().collect::<String>());
+            if classify_spots(&spots) == SpotBadge::Unknown {
+                let seen = UNKNOWN_BADGES.fetch_add(1, Ordering::Relaxed) + 1;
+                say!("⚠️ Unknown spots badge \"{}\" ({} unknown so far)", spots, seen);
+            }
+            spots
+        }
+        None => "N/A".into(),
+    };
+
+    // Prefer a dedicated price element, otherwise look for a price anywhere on the card
+    let price = instance
+        .select(&price_sel)
+        .next()
+        .and_then(|el| extract_price(&normalize_spots(&el.text().collect::<String>())))
+        .or_else(|| extract_price(&normalize_spots(&instance.text().collect::<String>())));
+
+    // Same for any membership or pass requirement
+    let membership = instance
+        .select(&membership_sel)
+        .next()
+        .map(|el| normalize_spots(&el.text().collect::<String>()))
+        .filter(|text| !text.is_empty())
+        .or_else(|| extract_membership(&normalize_spots(&instance.text().collect::<String>())));
+
+    InstanceDetails { spots, price, membership }
+}
+
+/// Pull a requirement like "Requires Student Membership" out of scraped text
+pub fn extract_membership(text: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets lined up with `text`
+    let lower = text.to_ascii_lowercase();
+    let start = lower.find("requires")? + "requires".len();
+    let requirement = text[start..]
+        .trim_start_matches([':', ' '])
+        .split(['.', ',', '|', '('])
+        .next()?
+        .trim();
+
+    let lower = requirement.to_lowercase();
+    (lower.contains("membership") || lower.contains("pass")).then(|| requirement.to_string())
+}
+
+/// Whether a session's membership requirement is covered by the configured memberships
+pub fn membership_allowed(requirement: Option<&str>, memberships: &[String]) -> bool {
+    let Some(requirement) = requirement else {
+        return true;
+    };
+    if memberships.is_empty() {
+        return true;
+    }
+
+    let requirement = requirement.to_lowercase();
+    memberships
+        .iter()
+        .any(|membership| requirement.contains(&membership.to_lowercase()))
+}
+
+/// Pull a price like "$5.00" or "Free" out of scraped text
+pub fn extract_price(text: &str) -> Option<String> {
+    if let Some(start) = text.find('$') {
+        let amount: String = text[start + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+            .collect();
+        let amount = amount.trim_end_matches(['.', ',']);
+        if !amount.is_empty() {
+            return Some(format!("${amount}"));
+        }
+    }
+
+    text.split_whitespace()
+        .any(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).eq_ignore_ascii_case("free"))
+        .then(|| "Free".to_string())
+}
+
+/// Whether a scraped price means the session costs nothing
+pub fn is_free_price(price: &str) -> bool {
+    price_amount(price) == Some(0.0)
+}
+
+/// Dollar amount of a scraped price like "$1,250.00", with "Free" as zero
+pub fn price_amount(price: &str) -> Option<f64> {
+    if price == "Free" {
+        return Some(0.0);
+    }
+    price.trim_start_matches('$').replace(',', "").parse().ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::notify::Notification;
+
+    /// A drop-in session on 2025-03-14 at 19:00, for tests to adjust
+    pub(crate) fn spot(program_name: &str, spots: &str) -> SpotInfo {
+        SpotInfo {
+            identity: String::new(),
+            program_id: "program".into(),
+            program_name: program_name.into(),
+            product_name: "Drop-in".into(),
+            location: "PAC".into(),
+            date: "2025-03-14".into(),
+            time: "19:00:00".into(),
+            end_time: "20:30:00".into(),
+            spots: spots.into(),
+            price: None,
+            membership: None,
+            observed_at: None,
+            stale: false,
+            portal_url: None,
+            also_listed_under: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pruning_drops_strings_nothing_else_holds() {
+        let pooled = |value: &str| INTERNER.lock().unwrap().contains(value);
+        let kept = intern("Interner test kept");
+        assert!(Arc::ptr_eq(&kept, &intern("Interner test kept")));
+        drop(intern("Interner test dropped"));
+
+        prune_interned();
+        assert!(pooled("Interner test kept"));
+        assert!(!pooled("Interner test dropped"));
+        // A pruned string comes back as a fresh copy when it's needed again
+        assert_eq!(&*intern("Interner test dropped"), "Interner test dropped");
+    }
+
+    #[test]
+    fn facility_dates_ignore_host_timezone() {
+        // Midnight in Toronto expressed in UTC is still the 14th at the facility
+        let utc = facility_datetime("2025-03-14T04:00:00Z").unwrap();
+        assert_eq!(utc.to_string(), "2025-03-14 00:00:00");
+
+        let naive = facility_datetime("2025-03-14T19:00:00").unwrap();
+        assert_eq!(naive.to_string(), "2025-03-14 19:00:00");
+
+        let fractional = facility_datetime("2025-03-14T19:00:00.000").unwrap();
+        assert_eq!(fractional.to_string(), "2025-03-14 19:00:00");
+
+        assert_eq!(facility_datetime("2025-03-14").unwrap().date().to_string(), "2025-03-14");
+        assert!(facility_datetime("soon").is_none());
+    }
+
+    #[test]
+    fn scraped_text_is_sanitized() {
+        assert_eq!(sanitize_text("Drop-in\r\nTitle: spoofed"), "Drop-in  Title: spoofed");
+        assert_eq!(sanitize_text("Court \u{202e}A\u{2066}B"), "Court AB");
+        assert_eq!(normalize_spots("2\u{0}\u{202e} spots\tleft"), "2 spots left");
+        assert_eq!(sanitize_text("Tennis 🎾 Café"), "Tennis 🎾 Café");
+    }
+
+    #[test]
+    fn appointments_keep_the_full_calendar_payload() {
+        let appts: Vec<Appointment> = serde_json::from_str(r#"[{
+            "ID": "a", "StartDate": "2025-03-14T19:00:00", "EndDate": "2025-03-14T20:30:00",
+            "Location": "PAC", "ProductName": "Drop-in", "ProductId": "p-1",
+            "ClassSize": 12, "NumberRegistered": 9, "NumberOnWaitlist": 0,
+            "InstructorFirstNameLastInitial": "Jane D.", "IsRecurring": true,
+            "RecurrenceInfo": "weekly", "AppointmentType": 0, "Status": 1, "AllDay": false
+        }]"#).unwrap();
+        let appt = &appts[0];
+        assert_eq!(appt.instructor_name.as_deref(), Some("Jane D."));
+        assert_eq!(appt.is_recurring, Some(true));
+        let availability = appt.availability().unwrap();
+        assert_eq!((availability.open, availability.capacity, availability.waitlist), (3, Some(12), Some(0)));
+
+        // Posted back as the site named them, leaving out what it didn't give
+        let fields = appt.known_fields();
+        assert!(fields.contains(&("ClassSize", "12".to_string())));
+        assert!(fields.contains(&("ProductId", "p-1".to_string())));
+        assert!(!fields.iter().any(|(name, _)| *name == "PortalURL"));
+
+        // Older payloads with just the basics still load
+        let basic: Appointment = serde_json::from_str(
+            r#"{"ID":"b","StartDate":"","EndDate":"","Location":"PAC","ProductName":"Drop-in"}"#,
+        ).unwrap();
+        assert!(basic.known_fields().is_empty() && basic.availability().is_none());
+    }
+
+    #[test]
+    fn appointments_survive_schema_drift() {
+        // camelCase names, numbers and booleans as strings, a new field and a missing one
+        let appts: Vec<Appointment> = serde_json::from_str(r#"[
+            {"id": 42, "startDate": "2025-03-14T19:00:00", "location": "PAC", "productName": "Drop-in",
+             "ClassSize": "12", "NumberRegistered": " 9 ", "IsRecurring": "True", "Status": null, "Coach": "Jane"},
+            {"ID": "b", "StartDate": "2025-03-14T20:30:00", "EndDate": "2025-03-14T22:00:00",
+             "Location": "PAC", "ProductName": "Drop-in", "ClassSize": "twelve"}
+        ]"#).unwrap();
+        let appt = &appts[0];
+        assert_eq!(appt.id, "42");
+        assert_eq!(appt.end_date, "");
+        assert_eq!((appt.class_size, appt.number_registered, appt.is_recurring, appt.status), (Some(12), Some(9), Some(true), None));
+        assert_eq!(appts[1].class_size, None);
+
+        let drift = SchemaDrift::of(&appts);
+        assert_eq!(drift.describe(), "unrecognized fields: Coach; missing fields: EndDate");
+        assert!(SchemaDrift::of(&appts[1..]).is_empty());
+    }
+
+    #[test]
+    fn links_point_to_the_session_page_when_known() {
+        let appts: Vec<Appointment> = serde_json::from_str(
+            r#"[{"ID":"a","StartDate":"","EndDate":"","Location":"PAC","ProductName":"Drop-in","PortalURL":"/Program/GetProgramDetails?courseId=a"}]"#,
+        ).unwrap();
+        assert_eq!(appts[0].portal_url.as_deref(), Some("/Program/GetProgramDetails?courseId=a"));
+
+        let mut session = spot("Beginner", "1 spot left");
+        assert_eq!(session.url(), crate::fetch::registration_url("program"));
+        session.portal_url = Some("/Program/GetProgramDetails?courseId=a".into());
+        assert_eq!(session.url(), format!("{}/Program/GetProgramDetails?courseId=a", crate::fetch::SITE_URL));
+        session.portal_url = Some("https://example.com/session/a".into());
+        assert_eq!(session.url(), "https://example.com/session/a");
+
+        let notification = Notification { spot: Some(session), ..Notification::new("Spot change", "") };
+        assert_eq!(notification.ntfy_headers().last(), Some(&("Click", "https://example.com/session/a".to_string())));
+    }
+
+    #[test]
+    fn unrecognized_badges_are_counted() {
+        let before = unknown_badges();
+        let card = "<div data-instance-appointmentid='a'><span class='spots-tag'>Ask at the front desk</span></div>";
+        assert_eq!(parse_instance(card, "a").spots, "Ask at the front desk");
+        // Other tests may be parsing unknown badges at the same time
+        assert!(unknown_badges() > before);
+    }
+
+    #[test]
+    fn odd_scraped_text_never_panics() {
+        // Quotes in an appointment ID used to make an invalid selector
+        let card = "<div data-instance-appointmentid=\"it's\"><span class='spots-tag'>2 spots left</span></div>";
+        assert_eq!(parse_instance(card, "it's").spots, "2 spots left");
+        assert_eq!(parse_instance(card, "x']").spots, "N/A");
+
+        // Lowercasing "İ" changes its length, which used to throw off the slice
+        assert_eq!(
+            extract_membership("İİ Requires Student Membership").as_deref(),
+            Some("Student Membership"),
+        );
+    }
+
+    #[test]
+    fn reconciles_calendar_dates_with_appointments() {
+        let appt = |id: &str, start: &str| Appointment {
+            id: id.into(),
+            start_date: start.into(),
+            end_date: String::new(),
+            location: "PAC".into(),
+            product_name: "Drop-in".into(),
+            ..Default::default()
+        };
+        let appts = [
+            appt("a", "2025-03-14T18:00:00"),
+            appt("b", "2025-03-14T20:00:00"),
+            appt("c", "2025-03-16T19:00:00"),
+            appt("d", "soon"),
+        ];
+        let dates = ["2025-03-14T00:00:00", "2025-03-15T00:00:00", "never"].map(String::from);
+
+        let calendar = reconcile(&appts, &dates);
+        assert_eq!(calendar.days.len(), 2);
+        assert_eq!(calendar.days.values().next().unwrap().len(), 2);
+        assert!(!calendar.is_consistent());
+        assert_eq!(
+            calendar.describe(),
+            "listed dates without sessions: 2025-03-15; sessions on unlisted dates: c; unreadable dates: never, soon (session d)",
+        );
+        assert!(reconcile(&appts[..2], &dates[..1]).is_consistent());
+    }
+
+    #[test]
+    fn classifies_spots_left() {
+        assert_eq!(classify_spots("1 spot left"), SpotBadge::SpotsLeft(1));
+        assert_eq!(classify_spots("3 spots left"), SpotBadge::SpotsLeft(3));
+        assert_eq!(classify_spots("2 spot(s) left"), SpotBadge::SpotsLeft(2));
+        assert_eq!(classify_spots("12 Spots Left"), SpotBadge::SpotsLeft(12));
+    }
+
+    #[test]
+    fn classifies_spot_counts_in_any_wording() {
+        let table = [
+            ("1 spot left", SpotBadge::SpotsLeft(1)),
+            ("3 of 12 spots left", SpotBadge::SpotsLeft(3)),
+            ("12 spots", SpotBadge::SpotsLeft(12)),
+            ("1 Spot", SpotBadge::SpotsLeft(1)),
+            ("Spots: 3", SpotBadge::SpotsLeft(3)),
+            ("Spots left: 0", SpotBadge::SpotsLeft(0)),
+            ("Available - 2", SpotBadge::SpotsLeft(2)),
+            ("4 places available", SpotBadge::SpotsLeft(4)),
+            ("7", SpotBadge::SpotsLeft(7)),
+            ("３ spots left", SpotBadge::SpotsLeft(3)),
+            ("Spots: ٥", SpotBadge::SpotsLeft(5)),
+            ("Spots: 3 or 4", SpotBadge::Unknown),
+            ("Spots: many", SpotBadge::Unknown),
+            ("Court 3", SpotBadge::Unknown),
+        ];
+        for (text, badge) in table {
+            assert_eq!(classify_spots(&normalize_spots(text)), badge, "{text}");
+        }
+
+        // Formats the built-in table doesn't know come from the config
+        let config: Config = toml::from_str(r#"
+            spot_patterns = ['^quedan (?<open>\d+) plazas$']
+            program_ids = []
+        "#).unwrap();
+        assert_eq!(classify_spots("Quedan 2 plazas"), SpotBadge::Unknown);
+        // Handed over directly, as the patterns set at startup are shared by every test
+        let patterns: Vec<Regex> = config.spot_patterns.iter().map(|pattern| pattern.0.clone()).collect();
+        assert_eq!(classify_with("Quedan 2 plazas", &patterns), Some(SpotBadge::SpotsLeft(2)));
+        assert!(toml::from_str::<Config>("spot_patterns = ['(\\d+) left']\nprogram_ids = []").is_err());
+    }
+
+    #[test]
+    fn classifies_register() {
+        assert_eq!(classify_spots("Register"), SpotBadge::Register);
+    }
+
+    #[test]
+    fn classifies_waitlist() {
+        assert_eq!(classify_spots("Waitlist"), SpotBadge::Waitlist);
+        assert_eq!(classify_spots("Join Waitlist"), SpotBadge::Waitlist);
+    }
+
+    #[test]
+    fn classifies_full() {
+        assert_eq!(classify_spots("FULL"), SpotBadge::Full);
+    }
+
+    #[test]
+    fn parses_availability_numbers() {
+        let availability = |text: &str| Availability::parse(&normalize_spots(text));
+        assert_eq!(availability("3 of 12 spots left"), Some(Availability { open: 3, capacity: Some(12), waitlist: None }));
+        assert_eq!(availability("1 spot left"), Some(Availability { open: 1, capacity: None, waitlist: None }));
+        assert_eq!(availability("Full"), Some(Availability { open: 0, capacity: None, waitlist: None }));
+        assert_eq!(availability("Waitlist (4)"), Some(Availability { open: 0, capacity: None, waitlist: Some(4) }));
+        assert_eq!(availability("2 on waitlist").unwrap().waitlist, Some(2));
+        assert_eq!(availability("Register"), None);
+    }
+
+    #[test]
+    fn flags_unknown_badges() {
+        assert_eq!(classify_spots("Cancelled"), SpotBadge::Unknown);
+        assert_eq!(classify_spots("many spots left"), SpotBadge::Unknown);
+    }
+}
@@ -0,0 +1,109 @@
+//! One token bucket for every request to the site, shared by all program checks, which
+//! also holds everyone back after a 429
+
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimitConfig;
+
+// Bucket state; starts full
+#[derive(Debug)]
+pub struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+    // Set from a 429's Retry-After; nothing goes out before then
+    paused_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    pub fn new(limits: &RateLimitConfig, now: Instant) -> Self {
+        TokenBucket { tokens: f64::from(limits.burst.get()), refilled_at: now, paused_until: None }
+    }
+
+    /// Take a token if one is available at `now`, otherwise say how long until one is
+    pub fn try_take(&mut self, limits: &RateLimitConfig, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.paused_until.filter(|until| *until > now) {
+            return Err(until - now);
+        }
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limits.requests_per_second).min(f64::from(limits.burst.get()));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / limits.requests_per_second.max(f64::EPSILON)))
+        }
+    }
+
+    /// Send nothing for `duration` from `now`, then start again with an empty bucket
+    pub fn pause(&mut self, duration: Duration, now: Instant) {
+        let until = now + duration;
+        self.paused_until = Some(self.paused_until.map_or(until, |paused| paused.max(until)));
+        self.tokens = 0.0;
+        self.refilled_at = until;
+    }
+}
+
+static SITE_BUCKET: LazyLock<Mutex<Option<TokenBucket>>> = LazyLock::new(Default::default);
+
+/// Wait for a turn to send a request to the site
+pub async fn acquire(limits: &RateLimitConfig) {
+    if limits.requests_per_second <= 0.0 {
+        return;
+    }
+    loop {
+        let wait = {
+            let mut bucket = SITE_BUCKET.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            match bucket.get_or_insert_with(|| TokenBucket::new(limits, now)).try_take(limits, now) {
+                Ok(()) => return,
+                Err(wait) => wait,
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Hold every request to the site back for `duration`, e.g. as a 429 asked
+pub fn pause(limits: &RateLimitConfig, duration: Duration) {
+    let mut bucket = SITE_BUCKET.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    bucket.get_or_insert_with(|| TokenBucket::new(limits, now)).pause(duration, now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn token_bucket_spaces_requests_and_honors_pauses() {
+        let limits = RateLimitConfig { requests_per_second: 2.0, burst: NonZeroU32::new(2).unwrap() };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(&limits, start);
+
+        assert!(bucket.try_take(&limits, start).is_ok());
+        assert!(bucket.try_take(&limits, start).is_ok());
+        assert_eq!(bucket.try_take(&limits, start), Err(Duration::from_millis(500)));
+        assert!(bucket.try_take(&limits, start + Duration::from_millis(500)).is_ok());
+
+        // A 429 holds everything back, then the bucket refills from empty
+        bucket.pause(Duration::from_secs(30), start + Duration::from_secs(1));
+        assert_eq!(bucket.try_take(&limits, start + Duration::from_secs(11)), Err(Duration::from_secs(20)));
+        assert_eq!(bucket.try_take(&limits, start + Duration::from_secs(31)), Err(Duration::from_millis(500)));
+        assert!(bucket.try_take(&limits, start + Duration::from_millis(31_500)).is_ok());
+    }
+
+    #[test]
+    fn zero_burst_is_rejected_when_loading() {
+        let config: crate::Config = toml::from_str("program_ids = []\n[rate_limit]\nburst = 1").unwrap();
+        let mut bucket = TokenBucket::new(&config.rate_limit, Instant::now());
+        assert!(bucket.try_take(&config.rate_limit, Instant::now()).is_ok());
+
+        let error = toml::from_str::<crate::Config>("program_ids = []\n[rate_limit]\nburst = 0").unwrap_err();
+        assert!(error.to_string().contains("burst = 0"), "{error}");
+        assert!(error.to_string().contains("nonzero"), "{error}");
+    }
+}
@@ -0,0 +1,198 @@
+//! Picking up config file changes without a restart, when the file is saved or on SIGHUP
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::error::Result;
+
+// How often the config file's modification time is looked at
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Reloads remembered for the status report
+const AUDIT_LENGTH: usize = 5;
+
+// Parts of key names whose values are never written to the log
+const SECRET_KEYS: [&str; 4] = ["password", "token", "secret", "webhook"];
+
+// The latest reloads, oldest first, each with when, why and what changed
+static AUDIT_TRAIL: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The latest config reloads, oldest first, e.g. "2025-03-14 18:00:00 on SIGHUP: interval_seconds: 60 → 30"
+pub fn audit_trail() -> Vec<String> {
+    AUDIT_TRAIL.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Read and parse a config file
+pub fn load(path: &Path) -> Result<Config> {
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Send every new version of the config at `path` that parses; broken edits are
+/// logged and skipped, so the monitor keeps running on the last good one
+pub fn watch(path: PathBuf) -> mpsc::Receiver<Config> {
+    watch_with(path, |_| {})
+}
+
+/// Like `watch`, passing each new version through `adjust` first, e.g. to keep
+/// command line overrides in place
+pub fn watch_with(path: PathBuf, adjust: impl Fn(&mut Config) + Send + 'static) -> mpsc::Receiver<Config> {
+    let (reloaded, reloads) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut hangup = Hangup::new();
+        let mut modified = modified_at(&path);
+        // What the running config was loaded from, to tell what each reload changes
+        let mut table = load_table(&path);
+        loop {
+            let hung_up = tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => false,
+                _ = hangup.recv() => true,
+            };
+            let now_modified = modified_at(&path);
+            if !hung_up && now_modified == modified {
+                continue;
+            }
+            modified = now_modified;
+
+            match load(&path) {
+                Ok(mut config) => {
+                    let new_table = load_table(&path);
+                    audit(if hung_up { "on SIGHUP" } else { "after the file was saved" }, &diff(&table, &new_table));
+                    table = new_table;
+                    adjust(&mut config);
+                    if reloaded.send(config).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("Failed to reload config from {}, keeping the current one: {}", path.display(), e),
+            }
+        }
+    });
+    reloads
+}
+
+/// Log what a reload changed and add it to the audit trail
+fn audit(cause: &str, changes: &[String]) {
+    let at = SystemClock.now().format("%Y-%m-%d %H:%M:%S");
+    say!("📝 Config reloaded {}, {} keys changed", cause, changes.len());
+    for change in changes {
+        say!("   {}", change);
+    }
+    let mut trail = AUDIT_TRAIL.lock().unwrap_or_else(|e| e.into_inner());
+    trail.push(format!("{} {}: {}", at, cause, if changes.is_empty() { "nothing".to_string() } else { changes.join("; ") }));
+    if trail.len() > AUDIT_LENGTH {
+        trail.remove(0);
+    }
+}
+
+/// The config file as plain TOML, or an empty table if it can't be read
+fn load_table(path: &Path) -> toml::Table {
+    fs::read_to_string(path).ok().and_then(|text| text.parse().ok()).unwrap_or_default()
+}
+
+/// `key: old → new` for every key whose value differs between two versions of the config,
+/// by dotted path, keeping secrets out
+pub fn diff(old: &toml::Table, new: &toml::Table) -> Vec<String> {
+    let (mut before, mut after) = (BTreeMap::new(), BTreeMap::new());
+    flatten("", &toml::Value::Table(old.clone()), &mut before);
+    flatten("", &toml::Value::Table(new.clone()), &mut after);
+
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    keys.into_iter()
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| {
+            let show = |value: Option<&String>| match value {
+                None => "(unset)".to_string(),
+                Some(_) if SECRET_KEYS.iter().any(|secret| key.contains(secret)) => "(hidden)".to_string(),
+                Some(value) => value.clone(),
+            };
+            format!("{}: {} → {}", key, show(before.get(key)), show(after.get(key)))
+        })
+        .collect()
+}
+
+/// Every value in `value` by its dotted path, descending into tables and arrays of tables
+fn flatten(path: &str, value: &toml::Value, out: &mut BTreeMap<String, String>) {
+    let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{path}.{key}") };
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten(&join(key), value, out);
+            }
+        }
+        toml::Value::Array(items) if !items.is_empty() && items.iter().all(toml::Value::is_table) => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(&format!("{path}[{index}]"), item, out);
+            }
+        }
+        value => {
+            out.insert(path.to_string(), value.to_string());
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// SIGHUP where there is one; never fires elsewhere
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        Hangup {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_name_each_changed_key_without_secrets() {
+        let old: toml::Table = toml::from_str(r#"
+            interval_seconds = 60
+            program_ids = [{ id = "1", name = "Beginner" }]
+            [[notifiers]]
+            type = "telegram"
+            bot_token = "123:abc"
+        "#).unwrap();
+        let new: toml::Table = toml::from_str(r#"
+            interval_seconds = 30
+            quiet_hours = "23:00-07:00"
+            program_ids = [{ id = "1", name = "Beginner" }, { id = "2", name = "Advanced" }]
+            [[notifiers]]
+            type = "telegram"
+            bot_token = "456:def"
+        "#).unwrap();
+
+        assert_eq!(diff(&old, &new), [
+            "interval_seconds: 60 → 30",
+            "notifiers[0].bot_token: (hidden) → (hidden)",
+            "program_ids[1].id: (unset) → \"2\"",
+            "program_ids[1].name: (unset) → \"Advanced\"",
+            "quiet_hours: (unset) → \"23:00-07:00\"",
+        ]);
+        assert!(diff(&new, &new).is_empty());
+    }
+}
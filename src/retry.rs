@@ -0,0 +1,99 @@
+//! Retrying transient failures with exponential backoff and jitter
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::RetryConfig;
+use crate::error::{Error, Result};
+
+// Set once from the config at startup, for callers that don't have the config at hand
+static POLICY: Mutex<RetryConfig> = Mutex::new(RetryConfig::DEFAULT);
+
+pub fn set_policy(policy: RetryConfig) {
+    *POLICY.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+pub fn policy() -> RetryConfig {
+    *POLICY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl RetryConfig {
+    /// Backoff before retry number `retry` (1 for the first): the base delay doubled
+    /// each time, capped, then scaled by `jitter` in 0..1 down to as little as half
+    pub fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let doubled = self.base_delay_ms.saturating_mul(1u64 << retry.saturating_sub(1).min(32));
+        let capped = doubled.min(self.max_delay_ms) as f64;
+        Duration::from_millis((capped * (0.5 + jitter.clamp(0.0, 1.0) / 2.0)) as u64)
+    }
+}
+
+/// A random number in 0..1, good enough to spread retries apart
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Run `attempt` until it succeeds, fails with an error that isn't transient, or the
+/// policy runs out of attempts; `what` names the operation in logs
+pub async fn with_retry<T, F, Fut>(policy: &RetryConfig, what: &str, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut tries = 1;
+    loop {
+        match attempt().await {
+            Err(e) if e.is_transient() && tries < policy.max_attempts => {
+                // Wait at least as long as a 429 asked
+                let delay = match &e {
+                    Error::RateLimited { retry_after: Some(retry_after), .. } => {
+                        policy.delay(tries, jitter()).max(*retry_after)
+                    }
+                    _ => policy.delay(tries, jitter()),
+                };
+                eprintln!(
+                    "{} failed ({} error, attempt {}/{}), retrying in {:.1}s: {}",
+                    what, e.kind(), tries, policy.max_attempts, delay.as_secs_f64(), e,
+                );
+                tokio::time::sleep(delay).await;
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn retries_transient_errors_with_growing_backoff() {
+        let policy = RetryConfig { max_attempts: 3, base_delay_ms: 100, max_delay_ms: 250 };
+        assert_eq!(policy.delay(1, 1.0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, 1.0), Duration::from_millis(200));
+        assert_eq!(policy.delay(3, 1.0), Duration::from_millis(250));
+        assert_eq!(policy.delay(3, 0.0), Duration::from_millis(125));
+
+        let instant = RetryConfig { base_delay_ms: 0, ..policy };
+        let attempts = Cell::new(0);
+        let result = with_retry(&instant, "test", || async {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(Error::notify("unreachable"))
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+
+        // Bad pages aren't worth another try
+        attempts.set(0);
+        let _ = with_retry(&instant, "test", || async {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(Error::Parse("missing #ApptInfo input".into()))
+        }).await;
+        assert_eq!(attempts.get(), 1);
+    }
+}
@@ -0,0 +1,38 @@
+//! Stopping cleanly on Ctrl-C or SIGTERM: the cycle in flight finishes first
+
+use tokio::sync::watch;
+
+/// Watch for a termination signal; the receiver flips to `true` on the first one,
+/// and a second one exits right away
+pub fn listen() -> watch::Receiver<bool> {
+    let (stop, stopping) = watch::channel(false);
+    tokio::spawn(async move {
+        if signalled().await.is_err() {
+            // Without signal handling the process can still be killed the usual way
+            return;
+        }
+        say!("🛑 Stopping after the current check, signal again to stop right away");
+        let _ = stop.send(true);
+        if signalled().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    stopping
+}
+
+/// Wait for Ctrl-C, or SIGTERM where there is one
+async fn signalled() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
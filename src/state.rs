@@ -0,0 +1,501 @@
+//! What the monitor remembers between cycles
+
+use chrono::{DateTime, NaiveDateTime};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::parse::{SpotBadge, SpotInfo, classify_spots};
+
+// Programs that fail this many checks in a row are considered failing, not just degraded
+pub const FAILING_AFTER: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthState {
+    Ok,
+    Degraded,
+    Failing,
+}
+
+impl std::fmt::Display for HealthState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HealthState::Ok => "ok",
+            HealthState::Degraded => "degraded",
+            HealthState::Failing => "failing",
+        })
+    }
+}
+
+// How a program's checks have been going across cycles
+#[derive(Debug, Clone)]
+pub struct ProgramHealth {
+    pub state: HealthState,
+    pub since: DateTime<Tz>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    // How many times in a row last_error has been the exact same message
+    pub repeats: u32,
+}
+
+impl ProgramHealth {
+    pub fn new(now: DateTime<Tz>) -> Self {
+        ProgramHealth { state: HealthState::Ok, since: now, consecutive_failures: 0, last_error: None, repeats: 0 }
+    }
+
+    /// Fold one check result into the health state
+    pub fn record(&mut self, result: std::result::Result<(), &Error>, now: DateTime<Tz>) {
+        match result {
+            Ok(()) => {
+                self.consecutive_failures = 0;
+                self.last_error = None;
+                self.repeats = 0;
+            }
+            Err(e) => {
+                let error = e.to_string();
+                self.repeats = if self.last_error.as_deref() == Some(error.as_str()) { self.repeats + 1 } else { 1 };
+                self.consecutive_failures += 1;
+                self.last_error = Some(error);
+            }
+        }
+
+        let state = match self.consecutive_failures {
+            0 => HealthState::Ok,
+            failures if failures < FAILING_AFTER => HealthState::Degraded,
+            _ => HealthState::Failing,
+        };
+        if state != self.state {
+            self.state = state;
+            self.since = now;
+        }
+    }
+
+    /// Whether the current error is worth a notification: the 1st, 3rd, 10th,
+    /// 30th, 100th, ... occurrence of the same message in a row
+    pub fn should_notify_error(&self) -> bool {
+        let mut step = 1;
+        while step <= self.repeats {
+            if self.repeats == step || self.repeats == step * 3 {
+                return true;
+            }
+            step *= 10;
+        }
+        false
+    }
+
+    /// e.g. "Beginner: failing since 2025-03-14 12:00 (3 failures, last: network error: ...)"
+    pub fn describe(&self, name: &str) -> String {
+        let since = self.since.format("%Y-%m-%d %H:%M");
+        match &self.last_error {
+            Some(error) => format!(
+                "{}: {} since {} ({} failures, last: {})",
+                name, self.state, since, self.consecutive_failures, error
+            ),
+            None => format!("{}: {} since {}", name, self.state, since),
+        }
+    }
+}
+
+/// Collapse the same physical session listed under several programs into one entry,
+/// keeping the first program's key so its history carries over and noting the others
+/// in `also_listed_under`
+pub fn merge_duplicates(spots: Vec<(String, SpotInfo)>) -> Vec<(String, SpotInfo)> {
+    let mut merged: Vec<(String, SpotInfo)> = Vec::new();
+    let mut seen: HashMap<[Arc<str>; 4], usize> = HashMap::new();
+
+    for (key, spot) in spots {
+        let physical = [
+            spot.location.clone(),
+            spot.date.clone(),
+            spot.time.clone(),
+            spot.product_name.clone(),
+        ];
+        match seen.get(&physical) {
+            Some(&index) if !spot.location.is_empty() && merged[index].1.program_id != spot.program_id => {
+                let existing = &mut merged[index].1;
+                if !existing.program_ids().any(|id| *id == spot.program_id) {
+                    existing.also_listed_under.push((spot.program_id, spot.program_name));
+                }
+            }
+            _ => {
+                seen.insert(physical, merged.len());
+                merged.push((key, spot));
+            }
+        }
+    }
+
+    merged
+}
+
+/// Compare two normalized spot strings, ignoring casing differences
+pub fn spots_changed(previous: &str, current: &str) -> bool {
+    previous.to_lowercase() != current.to_lowercase()
+}
+
+// Latest state of every session seen so far
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Sessions {
+    // By session key
+    pub spots: HashMap<String, SpotInfo>,
+    // Session identity to its latest key, so renumbered sessions keep their history
+    identities: HashMap<String, String>,
+}
+
+impl Sessions {
+    /// Remove and return the last state of a session, falling back to its identity
+    /// when the appointment ID changed; the flag is set when that fallback was used
+    pub fn take_previous(&mut self, key: &str, spot: &SpotInfo) -> Option<(SpotInfo, bool)> {
+        if let Some(prev) = self.spots.remove(key) {
+            return Some((prev, false));
+        }
+        let old_key = self.identities.get(&spot.identity)?;
+        self.spots.remove(old_key).map(|prev| (prev, true))
+    }
+
+    /// Load sessions saved by [`Sessions::save`]; a missing file means a fresh start
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Sessions::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut sessions: Sessions = serde_json::from_str(&text)?;
+        sessions.spots = sessions.spots.into_iter().map(|(key, spot)| (key, spot.interned())).collect();
+        Ok(sessions)
+    }
+
+    /// Write the sessions to `path`, via a temporary file so a crash mid-write
+    /// never leaves a truncated state behind
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, serde_json::to_vec(self)?)?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    /// Remove every session whose start time is at or before `now`, reporting how each
+    /// one ended; `listed` holds the keys the site still showed this cycle
+    pub fn close_passed(&mut self, now: NaiveDateTime, listed: &HashSet<String>) -> Vec<PassedSession> {
+        let passed: Vec<String> = self
+            .spots
+            .iter()
+            .filter(|(_, spot)| spot.start().is_some_and(|start| start <= now))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        passed
+            .into_iter()
+            .filter_map(|key| {
+                let spot = self.spots.remove(&key)?;
+                self.identities.remove(&spot.identity);
+                let lifecycle = if listed.contains(&key) { Lifecycle::Played } else { Lifecycle::Expired };
+                Some(PassedSession { lifecycle, badge: classify_spots(&spot.spots), spot })
+            })
+            .collect()
+    }
+
+    /// Flag every session whose spots haven't been refreshed within `max_age`,
+    /// returning how many are stale
+    pub fn mark_stale(&mut self, now: NaiveDateTime, max_age: chrono::Duration) -> usize {
+        self.spots
+            .values_mut()
+            .map(|spot| {
+                spot.stale = spot.is_stale(now, max_age);
+                spot.stale
+            })
+            .filter(|&stale| stale)
+            .count()
+    }
+
+    /// Keep only sessions listed under a program `keep` accepts, returning how many were
+    /// dropped; a session whose own program goes is handed to the next one listing it
+    pub fn retain_programs(&mut self, keep: impl Fn(&str) -> bool) -> usize {
+        let before = self.spots.len();
+        let spots = std::mem::take(&mut self.spots);
+        self.identities.clear();
+        for (key, mut spot) in spots {
+            spot.also_listed_under.retain(|(id, _)| keep(id));
+            if keep(&spot.program_id) {
+                self.insert(key, spot);
+            } else if !spot.also_listed_under.is_empty() {
+                // Keys and identities start with the program ID (see `check_program`),
+                // so this is where the next program's check will look for the session
+                let (id, name) = spot.also_listed_under.remove(0);
+                let rekey = |text: &str| match text.strip_prefix(&*spot.program_id) {
+                    Some(rest) => format!("{id}{rest}"),
+                    None => text.to_string(),
+                };
+                let key = rekey(&key);
+                spot.identity = rekey(&spot.identity);
+                (spot.program_id, spot.program_name) = (id, name);
+                self.insert(key, spot);
+            }
+        }
+        before - self.spots.len()
+    }
+
+    /// Record the current state of a session
+    pub fn insert(&mut self, key: String, spot: SpotInfo) {
+        self.identities.insert(spot.identity.clone(), key.clone());
+        self.spots.insert(key, spot);
+    }
+}
+
+// How a tracked session ended once its start time came around
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lifecycle {
+    // Still listed by the site when it started
+    Played,
+    // Dropped off the listing before it started (cancelled, or no longer offered)
+    Expired,
+}
+
+impl std::fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Lifecycle::Played => "played",
+            Lifecycle::Expired => "expired",
+        })
+    }
+}
+
+// Final record of a session whose start time has passed
+#[derive(Debug, Clone, Serialize)]
+pub struct PassedSession {
+    pub lifecycle: Lifecycle,
+    // Fill state at the last check before it started
+    #[serde(skip)]
+    pub badge: SpotBadge,
+    #[serde(flatten)]
+    pub spot: SpotInfo,
+}
+
+/// Append passed sessions to the JSON Lines history file at `path`
+pub fn append_history(path: &Path, passed: &[PassedSession]) -> Result<()> {
+    let mut lines = String::new();
+    for session in passed {
+        lines.push_str(&serde_json::to_string(session)?);
+        lines.push('\n');
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+// What the check loop shares with command handlers running alongside it
+#[derive(Debug, Default)]
+pub struct MonitorStatus {
+    // Checks are skipped while set
+    pub paused: AtomicBool,
+    // Tracked sessions as of the last finished cycle
+    pub spots: Mutex<Vec<SpotInfo>>,
+    // Spots badges no classifier recognized so far, as of the last finished cycle
+    pub unknown_badges: AtomicU64,
+    // The latest config reloads and what each changed, oldest first
+    pub config_changes: Mutex<Vec<String>>,
+}
+
+impl MonitorStatus {
+    /// Replace the published sessions with the latest ones, in date order
+    pub fn publish(&self, spots: &HashMap<String, SpotInfo>) {
+        let mut sorted: Vec<SpotInfo> = spots.values().cloned().collect();
+        sorted.sort_by(|a, b| (&a.date, &a.time, &a.program_name).cmp(&(&b.date, &b.time, &b.program_name)));
+        *self.spots.lock().unwrap_or_else(|e| e.into_inner()) = sorted;
+    }
+
+    /// Multi-line status report: paused or not, the latest config reloads, then one line
+    /// per tracked session
+    pub fn describe(&self) -> String {
+        let state = if self.paused.load(Ordering::Relaxed) { "⏸️ Paused" } else { "▶️ Running" };
+        let spots = self.spots.lock().unwrap_or_else(|e| e.into_inner());
+        let mut lines = vec![format!("{}, tracking {} sessions", state, spots.len())];
+        // A growing count means the site has started wording its badges differently
+        let unknown = self.unknown_badges.load(Ordering::Relaxed);
+        if unknown > 0 {
+            lines[0].push_str(&format!(", {} unrecognized spots badges seen", unknown));
+        }
+        let changes = self.config_changes.lock().unwrap_or_else(|e| e.into_inner());
+        lines.extend(changes.iter().map(|change| format!("📝 Config reloaded {}", change)));
+        lines.extend(spots.iter().map(|spot| {
+            let link = spot.portal_url.as_ref().map(|_| format!(" {}", spot.url())).unwrap_or_default();
+            format!(
+                "{} {} {} ({}): {}{}{}",
+                spot.date, spot.time, spot.programs_label(), spot.product_name, spot.spots, spot.staleness_note(), link,
+            )
+        }));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::clock::tests::ManualClock;
+    use crate::config::Config;
+    use crate::parse::tests::spot;
+
+    // The same session listed by Beginner and by Badminton (badminton's own ntfy topic,
+    // alerting only when spots fill up)
+    pub(crate) fn merged_session() -> (Config, String, SpotInfo) {
+        let config: Config = toml::from_str(r#"
+            ntfy_endpoint = "https://ntfy.sh/topic"
+            program_ids = [
+                { id = "program", name = "Beginner" },
+                { id = "badminton", name = "Badminton", ntfy_endpoint = "https://ntfy.sh/roommate", notify_on = "decrease" },
+            ]
+        "#).unwrap();
+        let mut beginner = spot("Beginner", "2 spots left");
+        beginner.identity = "program-2025-03-14-19:00:00-Drop-in".into();
+        let mut badminton = spot("Badminton", "2 spots left");
+        badminton.program_id = "badminton".into();
+        let merged = merge_duplicates(vec![
+            ("program-2025-03-14-19:00:00-a".to_string(), beginner),
+            ("badminton-2025-03-14-19:00:00-a".to_string(), badminton),
+        ]);
+        assert_eq!(merged.len(), 1);
+        let (key, session) = merged.into_iter().next().unwrap();
+        (config, key, session)
+    }
+
+    #[test]
+    fn repeated_errors_back_off() {
+        let now = ManualClock::at("2025-03-14 12:00").now();
+        let mut health = ProgramHealth::new(now);
+        let error = Error::Parse("no hdnDates".to_string());
+
+        let notified: Vec<u32> = (1..=120)
+            .filter(|_| {
+                health.record(Err(&error), now);
+                health.should_notify_error()
+            })
+            .collect();
+        assert_eq!(notified, vec![1, 3, 10, 30, 100]);
+
+        // A different error starts the count over
+        health.record(Err(&Error::Auth("401".to_string())), now);
+        assert!(health.should_notify_error());
+        assert_eq!(health.state, HealthState::Failing);
+    }
+
+    #[test]
+    fn sessions_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("uwtennis-state-{}.json", std::process::id()));
+        let mut sessions = Sessions::default();
+        let mut tracked = spot("Beginner", "2 spots left");
+        tracked.identity = "program-2025-03-14-19:00:00-Drop-in".into();
+        sessions.insert("program-2025-03-14-1".into(), tracked.clone());
+        sessions.save(&path).unwrap();
+
+        // The appointment was renumbered while we were down
+        let mut loaded = Sessions::load(&path).unwrap();
+        let (prev, rekeyed) = loaded.take_previous("program-2025-03-14-2", &tracked).unwrap();
+        assert!(rekeyed);
+        assert_eq!(prev.spots, "2 spots left");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Sessions::load(&path).unwrap().spots.is_empty());
+    }
+
+    #[test]
+    fn passed_sessions_are_closed_out() {
+        let mut sessions = Sessions::default();
+        sessions.insert("played".into(), spot("Beginner", "Full"));
+        let mut cancelled = spot("Advanced", "2 spots left");
+        cancelled.identity = "cancelled".into();
+        sessions.insert("cancelled".into(), cancelled);
+        let mut later = spot("Intermediate", "1 spot left");
+        later.time = "21:00:00".into();
+        later.identity = "later".into();
+        sessions.insert("later".into(), later);
+
+        let now = ManualClock::at("2025-03-14 19:30").now().naive_local();
+        let listed = HashSet::from(["played".to_string(), "later".to_string()]);
+        let mut passed = sessions.close_passed(now, &listed);
+        passed.sort_by_key(|session| session.lifecycle == Lifecycle::Expired);
+
+        assert_eq!(passed.len(), 2);
+        assert_eq!((passed[0].lifecycle, passed[0].badge), (Lifecycle::Played, SpotBadge::Full));
+        assert_eq!((passed[1].lifecycle, passed[1].badge), (Lifecycle::Expired, SpotBadge::SpotsLeft(2)));
+        assert_eq!(sessions.spots.keys().collect::<Vec<_>>(), ["later"]);
+    }
+
+    #[test]
+    fn sessions_go_stale_without_fresh_observations() {
+        let at = |time: &str| chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        let mut sessions = Sessions::default();
+        let mut fresh = spot("Beginner", "2 spots left");
+        fresh.observed_at = Some(at("2025-03-14 18:00"));
+        sessions.insert("fresh".into(), fresh);
+        sessions.insert("unknown".into(), spot("Beginner", "Full"));
+
+        assert_eq!(sessions.mark_stale(at("2025-03-14 18:03"), chrono::Duration::minutes(3)), 1);
+        assert_eq!(sessions.mark_stale(at("2025-03-14 18:05"), chrono::Duration::minutes(3)), 2);
+        assert_eq!(sessions.spots["fresh"].staleness_note(), " (stale, last seen 2025-03-14 18:00)");
+        assert_eq!(sessions.spots["unknown"].staleness_note(), " (stale)");
+    }
+
+    #[test]
+    fn merged_sessions_outlive_one_of_their_programs() {
+        let (_, key, session) = merged_session();
+        let mut sessions = Sessions::default();
+        sessions.insert(key, session);
+
+        // Beginner goes on reload, Badminton still lists the session
+        assert_eq!(sessions.retain_programs(|id| id == "badminton"), 0);
+        let kept = &sessions.spots["badminton-2025-03-14-19:00:00-a"];
+        assert_eq!((kept.program_id.as_ref(), kept.programs_label().as_str()), ("badminton", "Badminton"));
+        assert!(kept.identity.starts_with("badminton-"));
+
+        assert_eq!(sessions.retain_programs(|_| false), 1);
+        assert!(sessions.spots.is_empty());
+    }
+
+    #[test]
+    fn reloads_forget_the_sessions_of_removed_programs() {
+        let mut sessions = Sessions::default();
+        let mut beginner = spot("Beginner", "2 spots left");
+        beginner.identity = "program-2025-03-14-19:00:00-Drop-in".into();
+        sessions.insert("program-2025-03-14-19:00:00-a".into(), beginner.clone());
+        let mut advanced = spot("Advanced", "Full");
+        advanced.program_id = "advanced".into();
+        advanced.identity = "advanced-2025-03-14-19:00:00-Drop-in".into();
+        sessions.insert("advanced-2025-03-14-19:00:00-b".into(), advanced.clone());
+
+        // Advanced is taken out of the config
+        assert_eq!(sessions.retain_programs(|id| id == "program"), 1);
+        assert_eq!(sessions.spots.keys().collect::<Vec<_>>(), ["program-2025-03-14-19:00:00-a"]);
+        assert_eq!(sessions.retain_programs(|_| true), 0);
+        assert!(sessions.take_previous("advanced-2025-03-14-19:00:00-c", &advanced).is_none());
+        // Renumbered sessions of the programs still there are found by identity as before
+        assert!(sessions.take_previous("program-2025-03-14-19:00:00-z", &beginner).is_some());
+    }
+    #[test]
+    fn status_reports_unrecognized_badges() {
+        let status = MonitorStatus::default();
+        assert_eq!(status.describe(), "▶️ Running, tracking 0 sessions");
+        status.unknown_badges.store(3, Ordering::Relaxed);
+        assert_eq!(status.describe(), "▶️ Running, tracking 0 sessions, 3 unrecognized spots badges seen");
+    }
+
+    #[test]
+    fn status_lists_config_reloads() {
+        let status = MonitorStatus::default();
+        *status.config_changes.lock().unwrap() = vec!["2025-03-14 18:00:00 on SIGHUP: interval_seconds: 60 → 30".into()];
+        assert_eq!(
+            status.describe(),
+            "▶️ Running, tracking 0 sessions\n📝 Config reloaded 2025-03-14 18:00:00 on SIGHUP: interval_seconds: 60 → 30",
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Plain-text mode: console output and notifications without emoji or symbols,
+//! for screen readers and SMS gateways. Also how much console output there is.
+
+use std::sync::atomic::{AtomicBool, AtomicI8, Ordering};
+
+// Set once from the config at startup
+static PLAIN: AtomicBool = AtomicBool::new(false);
+// Set once from the command line: below 0 prints only errors, above 0 adds detail lines
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+pub fn set_verbosity(verbosity: i8) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> i8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Print a console line, stripped down to plain text in plain-text mode
+pub fn print(line: &str) {
+    if verbosity() < 0 {
+        return;
+    }
+    if is_plain() {
+        println!("{}", plain_text(line));
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Print a console line only when asked for verbose output
+pub fn print_detail(line: &str) {
+    if verbosity() > 0 {
+        print(line);
+    }
+}
+
+/// Spell out the symbols we use and drop every other emoji or pictograph,
+/// keeping letters in any script
+pub fn plain_text(text: &str) -> String {
+    let spelled = text
+        .replace("⬆️", "(up)")
+        .replace("⬇️", "(down)")
+        .replace(" → ", " to ")
+        .replace(" @ ", " at ")
+        .replace('~', "about ");
+
+    spelled
+        .lines()
+        .map(|line| {
+            let kept: String = line.chars().filter(|c| c.is_ascii() || c.is_alphanumeric()).collect();
+            kept.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
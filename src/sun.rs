@@ -0,0 +1,75 @@
+//! Sunset times, for skipping outdoor sessions that run into the dark
+
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::f64::consts::PI;
+
+use crate::clock::FACILITY_TZ;
+use crate::config::{FacilityConfig, SunsetGuardConfig};
+use crate::parse::SpotInfo;
+
+/// Local time the sun sets on `date` at `[latitude, longitude]`, using NOAA's
+/// approximation (good to a minute or two); `None` on days it doesn't set
+pub fn sunset(date: NaiveDate, coordinates: [f64; 2]) -> Option<NaiveTime> {
+    let [latitude, longitude] = coordinates;
+    let gamma = 2.0 * PI / 365.0 * (date.ordinal0() as f64 + 0.5);
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos() - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos() + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos() + 0.00148 * (3.0 * gamma).sin();
+
+    // Sun's upper edge at the horizon, allowing for refraction
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = 90.833f64.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    let minutes_utc = 720.0 - 4.0 * (longitude - hour_angle) - equation_of_time;
+    let midnight_utc = Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN));
+    let sunset = midnight_utc + chrono::Duration::seconds((minutes_utc * 60.0) as i64);
+    Some(sunset.with_timezone(&FACILITY_TZ).time())
+}
+
+impl SunsetGuardConfig {
+    /// Whether the session is on an outdoor court and still running after sunset
+    pub fn ends_after_sunset(&self, spot: &SpotInfo, facility: Option<&FacilityConfig>) -> bool {
+        let outdoor = self.locations.iter().any(|location| location.eq_ignore_ascii_case(&spot.location))
+            || facility.is_some_and(|facility| facility.outdoor);
+        let (Some(start), Ok(end)) = (spot.start(), NaiveTime::parse_from_str(&spot.end_time, "%H:%M:%S")) else {
+            return false;
+        };
+        let coordinates = facility.and_then(|facility| facility.coordinates).unwrap_or(self.coordinates);
+        outdoor && sunset(start.date(), coordinates).is_some_and(|sunset| end > sunset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn outdoor_sessions_ending_after_sunset_are_flagged() {
+        let campus = [43.4723, -80.5449];
+        let june = sunset(chrono::NaiveDate::from_ymd_opt(2025, 6, 21).unwrap(), campus).unwrap();
+        let december = sunset(chrono::NaiveDate::from_ymd_opt(2025, 12, 21).unwrap(), campus).unwrap();
+        // Published times for Waterloo are 21:05 and 16:46, NOAA's approximation is within a couple of minutes
+        let near = |time: NaiveTime, expected: &str| {
+            (time - NaiveTime::parse_from_str(expected, "%H:%M").unwrap()).num_minutes().abs() <= 2
+        };
+        assert!(near(june, "21:05"), "{june}");
+        assert!(near(december, "16:46"), "{december}");
+
+        let guard: crate::config::SunsetGuardConfig = toml::from_str(r#"locations = ["Outdoor Courts"]"#).unwrap();
+        let mut outdoor = spot("Beginner", "2 spots left");
+        outdoor.location = "outdoor courts".into();
+        assert!(guard.ends_after_sunset(&outdoor, None));
+        outdoor.date = "2025-06-21".into();
+        assert!(!guard.ends_after_sunset(&outdoor, None));
+        assert!(!guard.ends_after_sunset(&spot("Beginner", "2 spots left"), None));
+    }
+}
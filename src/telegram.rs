@@ -0,0 +1,221 @@
+//! Telegram bot: notifications and polls out, and /status, /pause and /resume commands in
+
+use reqwest::Client;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::config::TelegramConfig;
+use crate::error::{Error, Result};
+use crate::notify::{Delivered, Notification, Notifier, check_delivery, split_message};
+use crate::state::MonitorStatus;
+
+// Telegram rejects messages longer than 4096 characters
+pub const TELEGRAM_MAX_BYTES: usize = 4096;
+
+// How long each getUpdates call waits for a new message
+const POLL_TIMEOUT_SECONDS: u64 = 30;
+
+impl Notifier for TelegramConfig {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.send_resuming(notification, &Delivered::default()).await
+    }
+
+    async fn send_resuming(&self, notification: &Notification, delivered: &Delivered) -> Result<()> {
+        let text = format!("{}\n{}", notification.title, notification.message);
+        for part in split_message(&text, TELEGRAM_MAX_BYTES).into_iter().skip(delivered.parts()) {
+            send_message(self, &part).await?;
+            delivered.count_part();
+        }
+        Ok(())
+    }
+}
+
+/// Send a plain-text message to the configured chat
+async fn send_message(telegram: &TelegramConfig, text: &str) -> Result<()> {
+    let response = Client::new()
+        .post(api_url(telegram, "sendMessage"))
+        .json(&serde_json::json!({ "chat_id": telegram.chat_id, "text": text }))
+        .send()
+        .await
+        .map_err(Error::notify)?;
+    check_delivery(&response)?;
+    say!("✅ Telegram message sent");
+    Ok(())
+}
+
+/// Post a native poll to the configured chat, returning its message ID
+pub(crate) async fn send_poll(telegram: &TelegramConfig, question: &str, options: &[String]) -> Result<i64> {
+    let sent: Sent<SentMessage> = Client::new()
+        .post(api_url(telegram, "sendPoll"))
+        .json(&serde_json::json!({
+            "chat_id": telegram.chat_id,
+            "question": question,
+            "options": options.iter().map(|text| serde_json::json!({ "text": text })).collect::<Vec<_>>(),
+            "is_anonymous": false,
+            "allows_multiple_answers": true,
+        }))
+        .send()
+        .await
+        .map_err(Error::notify)?
+        .json()
+        .await
+        .map_err(Error::notify)?;
+    Ok(sent.result.message_id)
+}
+
+/// Close a poll and return the number of votes for each option, in order
+pub(crate) async fn stop_poll(telegram: &TelegramConfig, message_id: i64) -> Result<Vec<u32>> {
+    let stopped: Sent<Poll> = Client::new()
+        .post(api_url(telegram, "stopPoll"))
+        .json(&serde_json::json!({ "chat_id": telegram.chat_id, "message_id": message_id }))
+        .send()
+        .await
+        .map_err(Error::notify)?
+        .json()
+        .await
+        .map_err(Error::notify)?;
+    Ok(stopped.result.options.iter().map(|option| option.voter_count).collect())
+}
+
+fn api_url(telegram: &TelegramConfig, method: &str) -> String {
+    format!("https://api.telegram.org/bot{}/{}", telegram.bot_token, method)
+}
+
+// Successful Bot API response wrapping the method's result
+#[derive(Debug, Deserialize)]
+struct Sent<T> {
+    result: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct SentMessage {
+    message_id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Poll {
+    options: Vec<PollOption>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PollOption {
+    voter_count: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Updates {
+    result: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    #[serde(default)]
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+/// Long-poll the bot for commands from the configured chat, forever
+pub async fn poll_commands(telegram: TelegramConfig, status: Arc<MonitorStatus>) {
+    let client = Client::new();
+    let mut offset = 0;
+    say!("🤖 Listening for Telegram commands");
+
+    loop {
+        let updates = async {
+            let updates: Updates = client
+                .get(api_url(&telegram, "getUpdates"))
+                .query(&[("offset", offset.to_string()), ("timeout", POLL_TIMEOUT_SECONDS.to_string())])
+                .timeout(Duration::from_secs(POLL_TIMEOUT_SECONDS + 10))
+                .send()
+                .await?
+                .json()
+                .await?;
+            Ok::<_, Error>(updates)
+        }
+        .await;
+
+        let updates = match updates {
+            Ok(updates) => updates,
+            Err(e) => {
+                eprintln!("Failed to fetch Telegram commands: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        for update in updates.result {
+            offset = offset.max(update.update_id + 1);
+            // Only the configured chat gets to control the monitor
+            let Some(message) = update
+                .message
+                .filter(|message| telegram.chat_id.to_string() == message.chat.id.to_string())
+            else {
+                continue;
+            };
+            let Some(reply) = message.text.as_deref().and_then(|text| handle_command(text, &status)) else {
+                continue;
+            };
+            for part in split_message(&reply, TELEGRAM_MAX_BYTES) {
+                if let Err(e) = send_message(&telegram, &part).await {
+                    eprintln!("Failed to answer Telegram command: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Carry out a bot command and return the reply, or `None` for anything else
+pub(crate) fn handle_command(text: &str, status: &MonitorStatus) -> Option<String> {
+    // Commands in groups arrive as "/status@BotName"
+    let command = text.split_whitespace().next()?.split('@').next()?;
+    match command {
+        "/status" => Some(status.describe()),
+        "/pause" => {
+            status.paused.store(true, Ordering::Relaxed);
+            say!("⏸️ Paused from Telegram");
+            Some("⏸️ Paused, no checks until /resume".into())
+        }
+        "/resume" => {
+            status.paused.store(false, Ordering::Relaxed);
+            say!("▶️ Resumed from Telegram");
+            Some("▶️ Resumed".into())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn telegram_commands_pause_and_report() {
+        let status = MonitorStatus::default();
+        status.publish(&HashMap::from([("key".to_string(), spot("Beginner", "2 spots left"))]));
+
+        assert!(handle_command("/pause@TennisBot", &status).is_some());
+        assert!(status.paused.load(Ordering::Relaxed));
+        let report = handle_command("/status", &status).unwrap();
+        assert_eq!(report, "⏸️ Paused, tracking 1 sessions\n2025-03-14 19:00:00 Beginner (Drop-in): 2 spots left");
+
+        handle_command("/resume", &status);
+        assert!(!status.paused.load(Ordering::Relaxed));
+        assert!(handle_command("hello", &status).is_none());
+    }
+}
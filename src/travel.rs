@@ -0,0 +1,112 @@
+//! Rough travel times from home to a session's facility
+
+use chrono::NaiveDateTime;
+
+use crate::config::{FacilityConfig, TravelConfig, TravelMode};
+use crate::parse::SpotInfo;
+
+// Roads and paths are rarely straight; stretch the as-the-crow-flies distance by this much
+const DETOUR_FACTOR: f64 = 1.3;
+
+impl TravelMode {
+    /// Average speed in km/h
+    fn speed(self) -> f64 {
+        match self {
+            TravelMode::Walk => 5.0,
+            TravelMode::Bike => 15.0,
+            TravelMode::Transit => 20.0,
+            TravelMode::Drive => 40.0,
+        }
+    }
+
+    /// Fixed minutes on top of the trip itself: waiting for a bus, parking
+    fn overhead_minutes(self) -> f64 {
+        match self {
+            TravelMode::Walk | TravelMode::Bike => 0.0,
+            TravelMode::Transit => 10.0,
+            TravelMode::Drive => 5.0,
+        }
+    }
+
+    /// Icon and phrase for alerts, e.g. ("🚲", "by bike")
+    fn describe(self) -> (&'static str, &'static str) {
+        match self {
+            TravelMode::Walk => ("🚶", "on foot"),
+            TravelMode::Bike => ("🚲", "by bike"),
+            TravelMode::Transit => ("🚌", "by transit"),
+            TravelMode::Drive => ("🚗", "by car"),
+        }
+    }
+}
+
+impl TravelConfig {
+    /// Estimated minutes from home to the session's facility, when its coordinates are known
+    /// from `facilities` or the facility registry entry
+    pub fn minutes_to(&self, spot: &SpotInfo, facility: Option<&FacilityConfig>) -> Option<i64> {
+        let coordinates = self
+            .facilities
+            .iter()
+            .find(|(location, _)| location.eq_ignore_ascii_case(&spot.location))
+            .map(|(_, coordinates)| *coordinates)
+            .or_else(|| facility?.coordinates)?;
+        let hours = distance_km(self.home, coordinates) * DETOUR_FACTOR / self.mode.speed();
+        Some((hours * 60.0 + self.mode.overhead_minutes()).ceil() as i64)
+    }
+
+    /// Whether I could still get there before the session starts, leaving `now`
+    pub fn reachable(&self, spot: &SpotInfo, facility: Option<&FacilityConfig>, now: NaiveDateTime) -> bool {
+        match (spot.start(), self.minutes_to(spot, facility)) {
+            (Some(start), Some(minutes)) => now + chrono::Duration::minutes(minutes) <= start,
+            _ => true,
+        }
+    }
+
+    /// A line like "🚲 ~12 min by bike" for an alert, or nothing
+    pub fn note(&self, spot: &SpotInfo, facility: Option<&FacilityConfig>) -> String {
+        self.minutes_to(spot, facility)
+            .map(|minutes| {
+                let (icon, how) = self.mode.describe();
+                format!("\n{icon} ~{minutes} min {how}")
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Great-circle distance between two [latitude, longitude] points
+pub fn distance_km(from: [f64; 2], to: [f64; 2]) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    let (lat1, lat2) = (from[0].to_radians(), to[0].to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (to[1] - from[1]).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::Clock;
+    use crate::clock::tests::ManualClock;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn sessions_too_soon_to_reach_are_skipped() {
+        let travel: crate::config::TravelConfig = toml::from_str(r#"
+            home = [43.4643, -80.5204]
+            mode = "bike"
+            facilities = { pac = [43.4722, -80.5460] }
+        "#).unwrap();
+        let session = spot("Beginner", "1 spot left");
+
+        // About 2.2 km, so a little over 11 minutes on a bike
+        assert_eq!(travel.minutes_to(&session, None), Some(12));
+        assert_eq!(travel.note(&session, None), "\n🚲 ~12 min by bike");
+        assert!(travel.reachable(&session, None, ManualClock::at("2025-03-14 18:48").now().naive_local()));
+        assert!(!travel.reachable(&session, None, ManualClock::at("2025-03-14 18:49").now().naive_local()));
+
+        // Unknown facilities don't block anything
+        let mut elsewhere = session.clone();
+        elsewhere.location = "CIF".into();
+        assert!(travel.reachable(&elsewhere, None, ManualClock::at("2025-03-14 18:59").now().naive_local()));
+        assert_eq!(travel.note(&elsewhere, None), "");
+    }
+}
@@ -0,0 +1,190 @@
+//! Weekly poll for picking which open session the group plays
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+
+use crate::config::{DiscordConfig, NotifierConfig, NotifierEntry, TelegramConfig, VotingConfig};
+use crate::error::Result;
+use crate::notify::{Notification, discord_poll_counts, notify_all, send_discord_poll};
+use crate::parse::{SpotInfo, classify_spots};
+use crate::telegram;
+
+// Both Telegram and Discord polls take at most 10 options
+pub const MAX_POLL_OPTIONS: usize = 10;
+
+const QUESTION: &str = "Which session should we play this week?";
+
+// A poll posted to one chat
+enum PostedPoll {
+    Telegram(TelegramConfig, i64),
+    Discord(DiscordConfig, String),
+}
+
+// The poll currently open for votes
+struct OpenPoll {
+    closes_at: DateTime<Tz>,
+    options: Vec<SpotInfo>,
+    posted: Vec<PostedPoll>,
+}
+
+// Posts the weekly poll and announces its winner
+pub struct WeeklyVote {
+    config: VotingConfig,
+    // Day the last poll was posted, so it goes out once per week
+    last_posted: Option<NaiveDate>,
+    open: Option<OpenPoll>,
+}
+
+impl WeeklyVote {
+    pub fn new(config: VotingConfig) -> Self {
+        WeeklyVote { config, last_posted: None, open: None }
+    }
+
+    /// Post this week's poll once it's due, and announce the winner once voting closes
+    pub async fn tick(&mut self, now: DateTime<Tz>, spots: &HashMap<String, SpotInfo>, notifiers: &[NotifierEntry]) {
+        if let Some(poll) = self.open.take_if(|poll| now >= poll.closes_at) {
+            announce_winner(poll, notifiers).await;
+        }
+
+        let due = now.weekday() == self.config.day
+            && now.time() >= self.config.time
+            && self.last_posted != Some(now.date_naive());
+        if !due || self.open.is_some() {
+            return;
+        }
+        self.last_posted = Some(now.date_naive());
+
+        let closes_at = now + chrono::Duration::hours(self.config.hours as i64);
+        let options = poll_options(
+            spots,
+            closes_at.naive_local(),
+            now.naive_local() + chrono::Duration::days(self.config.days as i64),
+        );
+        if options.len() < 2 {
+            say!("🗳️ {} open sessions to vote on this week, skipping the poll", options.len());
+            return;
+        }
+
+        let labels: Vec<String> = options.iter().map(option_label).collect();
+        let mut posted = Vec::new();
+        for entry in notifiers {
+            let result = match &entry.notifier {
+                NotifierConfig::Telegram(telegram) => telegram::send_poll(telegram, QUESTION, &labels)
+                    .await
+                    .map(|message_id| PostedPoll::Telegram(telegram.clone(), message_id)),
+                NotifierConfig::Discord(discord) => send_discord_poll(discord, QUESTION, &labels, self.config.hours)
+                    .await
+                    .map(|message_id| PostedPoll::Discord(discord.clone(), message_id)),
+                _ => continue,
+            };
+            match result {
+                Ok(poll) => posted.push(poll),
+                Err(e) => eprintln!("Failed to post poll to {}: {}", entry, e),
+            }
+        }
+
+        if posted.is_empty() {
+            eprintln!("No Telegram or Discord notifier took the weekly poll");
+            return;
+        }
+        say!("🗳️ Posted a poll with {} sessions, closing at {}", options.len(), closes_at.format("%a %H:%M"));
+        self.open = Some(OpenPoll { closes_at, options, posted });
+    }
+}
+
+/// Open sessions starting between `after` and `until`, earliest first, as many as a poll can hold
+pub fn poll_options(spots: &HashMap<String, SpotInfo>, after: NaiveDateTime, until: NaiveDateTime) -> Vec<SpotInfo> {
+    let mut options: Vec<SpotInfo> = spots
+        .values()
+        .filter(|spot| classify_spots(&spot.spots).is_open())
+        .filter(|spot| spot.start().is_some_and(|start| start > after && start <= until))
+        .cloned()
+        .collect();
+    options.sort_by(|a, b| (a.start(), &a.program_name).cmp(&(b.start(), &b.program_name)));
+    options.truncate(MAX_POLL_OPTIONS);
+    options
+}
+
+/// Short poll option like "Fri Mar 14 19:00 Beginner"
+pub fn option_label(spot: &SpotInfo) -> String {
+    match spot.start() {
+        Some(start) => format!("{} {}", start.format("%a %b %-d %H:%M"), spot.programs_label()),
+        None => format!("{} {} {}", spot.date, spot.time, spot.programs_label()),
+    }
+}
+
+/// The option with the most votes, earliest first on a tie; `None` when nobody voted
+pub fn winner(options: &[SpotInfo], votes: &[u32]) -> Option<(SpotInfo, u32)> {
+    options
+        .iter()
+        .zip(votes)
+        .filter(|(_, votes)| **votes > 0)
+        .rev()
+        .max_by_key(|(_, votes)| **votes)
+        .map(|(spot, votes)| (spot.clone(), *votes))
+}
+
+async fn announce_winner(poll: OpenPoll, notifiers: &[NotifierEntry]) {
+    // Votes from every chat the poll went to are added up
+    let mut votes = vec![0; poll.options.len()];
+    for posted in &poll.posted {
+        match poll_counts(posted, poll.options.len()).await {
+            Ok(counts) => votes.iter_mut().zip(counts).for_each(|(total, count)| *total += count),
+            Err(e) => eprintln!("Failed to collect poll votes: {}", e),
+        }
+    }
+
+    let Some((spot, count)) = winner(&poll.options, &votes) else {
+        say!("🗳️ Poll closed without any votes");
+        return;
+    };
+    say!("🗳️ Poll winner: {} with {} votes", option_label(&spot), count);
+    notify_all(notifiers, &Notification {
+        spot: Some(spot.clone()),
+        ..Notification::new(
+            format!("Poll winner: {}", spot.product_name),
+            format!("{} ({}) on {} @ {} won with {} votes",
+                spot.programs_label(), spot.product_name, spot.date, spot.time, count),
+        )
+    }).await;
+}
+
+async fn poll_counts(posted: &PostedPoll, options: usize) -> Result<Vec<u32>> {
+    match posted {
+        PostedPoll::Telegram(telegram, message_id) => telegram::stop_poll(telegram, *message_id).await,
+        PostedPoll::Discord(discord, message_id) => discord_poll_counts(discord, message_id, options).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::clock::tests::ManualClock;
+    use crate::parse::tests::spot;
+
+    #[test]
+    fn polls_offer_upcoming_open_sessions_and_pick_the_most_voted() {
+        let session = |key: &str, date: &str, spots: &str| {
+            let mut session = spot(key, spots);
+            session.date = date.into();
+            (key.to_string(), session)
+        };
+        let spots = HashMap::from([
+            session("Tomorrow", "2025-03-15", "2 spots left"),
+            session("Monday", "2025-03-17", "Register"),
+            session("Tuesday", "2025-03-18", "Full"),
+            session("Next week", "2025-03-22", "1 spot left"),
+            session("Later", "2025-03-24", "1 spot left"),
+        ]);
+        let now = ManualClock::at("2025-03-14 18:00").now().naive_local();
+
+        let options = poll_options(&spots, now, now + chrono::Duration::days(9));
+        let labels: Vec<String> = options.iter().map(option_label).collect();
+        assert_eq!(labels, ["Sat Mar 15 19:00 Tomorrow", "Mon Mar 17 19:00 Monday", "Sat Mar 22 19:00 Next week"]);
+
+        assert_eq!(winner(&options, &[1, 3, 3]).unwrap().0.program_name, options[1].program_name);
+        assert!(winner(&options, &[0, 0, 0]).is_none());
+    }
+}
@@ -0,0 +1,368 @@
+//! End-to-end tests that point the monitor at the bundled fake site
+
+mod fake_site;
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use uwtennis::clock::{Clock, SystemClock};
+use uwtennis::fetch::{build_client, check_program};
+use uwtennis::{Config, check_all, debug, run, run_once, run_until};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn notifies_spot_change_within_an_interval() {
+    let opens_at = Duration::from_millis(2500);
+    let site = fake_site::start(vec![(Duration::ZERO, "Full"), (opens_at, "2 spots left")]).await;
+    let (url, started) = (&site.url, site.started);
+
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 1
+        site_url = "{url}"
+        rate_limit = {{ requests_per_second = 0 }}
+
+        [[program_ids]]
+        id = "fake-program"
+        name = "Beginner"
+
+        [[notifiers]]
+        type = "debug"
+        buffer = "e2e"
+    "#)).unwrap();
+    let monitor = tokio::spawn(run(Arc::new(config)));
+
+    // Nothing is sent while the session stays full
+    tokio::time::sleep(opens_at - Duration::from_millis(500)).await;
+    assert!(debug::recorded("e2e").is_empty());
+
+    let notification = loop {
+        if let Some(notification) = debug::recorded("e2e").into_iter().next() {
+            break notification;
+        }
+        assert!(started.elapsed() < opens_at + Duration::from_secs(3), "no notification after the spots opened");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    monitor.abort();
+
+    // The first check after the change catches it
+    assert!(started.elapsed() >= opens_at);
+    assert!(started.elapsed() < opens_at + Duration::from_millis(1500));
+    assert_eq!(notification.title, "Spot change: Drop-in");
+    assert!(notification.message.contains("2 spots left"));
+    assert_eq!(notification.previous_spots.as_deref(), Some("Full"));
+    assert_eq!(debug::recorded("e2e").len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn broadcasts_only_when_a_session_opens() {
+    let (opens_at, fills_at) = (Duration::from_millis(1500), Duration::from_millis(3000));
+    let site = fake_site::start(vec![(Duration::ZERO, "Full"), (opens_at, "2 spots left"), (fills_at, "1 spot left")]).await;
+    let (url, started) = (&site.url, site.started);
+
+    // The first try at the announcement fails, so it only gets through on the retry
+    let (endpoint, broadcasts) = recorder(vec![503]).await;
+
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 1
+        site_url = "{url}"
+        rate_limit = {{ requests_per_second = 0 }}
+        program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+        broadcast = {{ endpoint = "{endpoint}", template = "{{spots}}" }}
+
+        [[notifiers]]
+        type = "debug"
+        buffer = "e2e-broadcast"
+    "#)).unwrap();
+    let monitor = tokio::spawn(run(Arc::new(config)));
+
+    while debug::recorded("e2e-broadcast").len() < 2 {
+        assert!(started.elapsed() < fills_at + Duration::from_secs(3), "missed a spot change");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // The broadcast goes out after the notifiers have been sent theirs
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    monitor.abort();
+
+    // Both changes reach the notifiers, but only the opening is announced (tried twice)
+    assert_eq!(*broadcasts.lock().unwrap(), ["2 spots left", "2 spots left"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn quiet_hours_silence_every_channel() {
+    let opens_at = Duration::from_millis(1000);
+    let site = fake_site::start(vec![(Duration::ZERO, "Full"), (opens_at, "2 spots left")]).await;
+    let url = &site.url;
+    let (endpoint, sent) = recorder(vec![]).await;
+    // An hour either side of now, so the whole test runs inside it
+    let now = SystemClock.now();
+    let quiet_hours = format!(
+        "{}-{}",
+        (now - chrono::Duration::hours(1)).format("%H:%M"),
+        (now + chrono::Duration::hours(1)).format("%H:%M"),
+    );
+
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 1
+        site_url = "{url}"
+        rate_limit = {{ requests_per_second = 0 }}
+        quiet_hours = "{quiet_hours}"
+        program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+        broadcast = {{ endpoint = "{endpoint}" }}
+        users = [{{ name = "Sam", ntfy_endpoint = "{endpoint}" }}]
+
+        [[notifiers]]
+        type = "debug"
+        buffer = "e2e-quiet"
+    "#)).unwrap();
+    let monitor = tokio::spawn(run(Arc::new(config)));
+    tokio::time::sleep(opens_at + Duration::from_millis(2000)).await;
+    monitor.abort();
+
+    // The change was seen, but nobody hears about it until the morning
+    assert!(debug::recorded("e2e-quiet").is_empty());
+    assert!(sent.lock().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn users_choose_regardless_of_the_owners_filters() {
+    let drops_at = Duration::from_millis(1000);
+    let site = fake_site::start(vec![(Duration::ZERO, "3 spots left"), (drops_at, "2 spots left")]).await;
+    let url = &site.url;
+    let (endpoint, sent) = recorder(vec![]).await;
+
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 1
+        site_url = "{url}"
+        rate_limit = {{ requests_per_second = 0 }}
+        on_first_seen = "notify_if_available"
+        program_ids = [{{ id = "fake-program", name = "Beginner", min_spots = 5 }}]
+        users = [{{ name = "Sam", ntfy_endpoint = "{endpoint}" }}]
+
+        [[notifiers]]
+        type = "debug"
+        buffer = "e2e-users"
+    "#)).unwrap();
+    let monitor = tokio::spawn(run(Arc::new(config)));
+    tokio::time::sleep(drops_at + Duration::from_millis(2000)).await;
+    monitor.abort();
+
+    // Too few spots for the owner's min_spots, but Sam hears about the new session and the change
+    assert!(debug::recorded("e2e-users").is_empty());
+    // (the fake site lists two sessions, and only one of them changes)
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.iter().filter(|body| body.ends_with(": 3 spots left")).count(), 2, "{sent:?}");
+    assert_eq!(sent.iter().filter(|body| body.ends_with("3 spots left → 2 spots left")).count(), 1, "{sent:?}");
+}
+
+#[tokio::test]
+async fn tracks_every_session_on_a_date() {
+    let site = fake_site::start(vec![]).await;
+    let url = &site.url;
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 60
+        site_url = "{url}"
+        program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+    "#)).unwrap();
+
+    let client = build_client().unwrap();
+    let spots = check_program(&client, &config, &config.program_ids[0]).await.unwrap();
+    let mut times: Vec<&str> = spots.values().map(|spot| &*spot.time).collect();
+    times.sort();
+    assert_eq!(times, ["17:30:00", "19:00:00"]);
+    assert_eq!(spots.len(), fake_site::SESSIONS.len());
+    // Both sessions' cards came back from one request
+    assert_eq!(site.filter_requests.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn check_lists_every_session_soonest_first() {
+    let site = fake_site::start(vec![(Duration::ZERO, "2 spots left")]).await;
+    let url = &site.url;
+    let config: Config = toml::from_str(&format!(r#"
+        site_url = "{url}"
+        program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+    "#)).unwrap();
+
+    let snapshot = check_all(Arc::new(config)).await.unwrap();
+    let sessions: Vec<(&str, &str, bool)> =
+        snapshot.sessions.iter().map(|session| (session.time.as_str(), session.spots.as_str(), session.open)).collect();
+    assert_eq!(sessions, [("17:30:00", "3 spots left", true), ("19:00:00", "2 spots left", true)]);
+}
+
+#[tokio::test]
+async fn reuses_the_calendar_within_its_ttl() {
+    let opens_at = Duration::from_secs(1);
+    let site = fake_site::start(vec![(Duration::ZERO, "Full"), (opens_at, "1 spot left")]).await;
+    let (url, started) = (&site.url, site.started);
+    let config: Config = toml::from_str(&format!(r#"
+        site_url = "{url}"
+        catalog_ttl_seconds = 3600
+        rate_limit = {{ requests_per_second = 0 }}
+        program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+    "#)).unwrap();
+
+    let client = build_client().unwrap();
+    let evening = |spots: &std::collections::HashMap<String, uwtennis::SpotInfo>| {
+        spots.values().find(|spot| &*spot.time == "19:00:00").unwrap().spots.clone()
+    };
+    let first = check_program(&client, &config, &config.program_ids[0]).await.unwrap();
+    tokio::time::sleep(opens_at.saturating_sub(started.elapsed())).await;
+    let second = check_program(&client, &config, &config.program_ids[0]).await.unwrap();
+
+    // Spot counts are fresh every time, the calendar only once
+    assert_eq!((evening(&first).as_str(), evening(&second).as_str()), ("Full", "1 spot left"));
+    assert_eq!(site.calendar_requests.load(Ordering::Relaxed), 1);
+    assert_eq!(site.filter_requests.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn stops_cleanly_and_saves_state() {
+    let site = fake_site::start(vec![(Duration::ZERO, "2 spots left")]).await;
+    let url = &site.url;
+    let state_path = std::env::temp_dir().join(format!("uwtennis-e2e-state-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 3600
+        site_url = "{url}"
+        state_path = "{state}"
+        notify_on_shutdown = true
+        program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+
+        [[notifiers]]
+        type = "debug"
+        buffer = "shutdown"
+    "#, state = state_path.display())).unwrap();
+
+    let (stop, stopping) = tokio::sync::watch::channel(false);
+    let monitor = tokio::spawn(run_until(Arc::new(config), stopping, tokio::sync::mpsc::channel(1).1));
+    // The first check runs right away, then the monitor sleeps for an hour
+    while !state_path.exists() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    std::fs::remove_file(&state_path).unwrap();
+    stop.send(true).unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(5), monitor).await.expect("monitor didn't stop");
+    assert!(result.unwrap().is_ok());
+    assert!(std::fs::read_to_string(&state_path).unwrap().contains("2 spots left"));
+    let sent = debug::recorded("shutdown");
+    assert_eq!(sent.last().map(|notification| notification.title.as_str()), Some("Monitor stopping"));
+    let _ = std::fs::remove_file(&state_path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sums_up_what_changed_while_stopped() {
+    let state_path = std::env::temp_dir().join(format!("uwtennis-e2e-away-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let config = |url: &str| -> Config {
+        toml::from_str(&format!(r#"
+            interval_seconds = 3600
+            site_url = "{url}"
+            state_path = "{state}"
+            program_ids = [{{ id = "fake-program", name = "Beginner" }}]
+
+            [[notifiers]]
+            type = "debug"
+            buffer = "e2e-away"
+        "#, state = state_path.display())).unwrap()
+    };
+
+    // Last seen full before the monitor went down
+    let before = fake_site::start(vec![(Duration::ZERO, "Full")]).await;
+    run_once(Arc::new(config(&before.url))).await.unwrap();
+    assert!(debug::recorded("e2e-away").is_empty());
+
+    // The scripted session opened up in the meantime
+    let after = fake_site::start(vec![(Duration::ZERO, "2 spots left")]).await;
+    let (stop, stopping) = tokio::sync::watch::channel(false);
+    let monitor = tokio::spawn(run_until(Arc::new(config(&after.url)), stopping, tokio::sync::mpsc::channel(1).1));
+    let started = std::time::Instant::now();
+    while debug::recorded("e2e-away").is_empty() {
+        assert!(started.elapsed() < Duration::from_secs(5), "no digest after the restart");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    stop.send(true).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), monitor).await.unwrap().unwrap().unwrap();
+
+    let sent = debug::recorded("e2e-away");
+    assert_eq!(sent.len(), 1, "{sent:?}");
+    assert_eq!(sent[0].title, "While you were away: Spot change: Drop-in");
+    assert!(sent[0].message.contains("Full → 2 spots left"), "{}", sent[0].message);
+    let _ = std::fs::remove_file(&state_path);
+}
+
+#[tokio::test]
+async fn once_checks_every_program_and_returns() {
+    let site = fake_site::start(vec![(Duration::ZERO, "2 spots left")]).await;
+    let state_path = std::env::temp_dir().join(format!("uwtennis-e2e-once-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    // The cron program isn't due until next year, but a single run checks it anyway
+    let config: Config = toml::from_str(&format!(r#"
+        interval_seconds = 3600
+        site_url = "{url}"
+        state_path = "{state}"
+        program_ids = [{{ id = "fake-program", name = "Beginner", cron = "0 0 1 1 *" }}]
+    "#, url = site.url, state = state_path.display())).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), run_once(Arc::new(config))).await.expect("didn't return").unwrap();
+    assert!(std::fs::read_to_string(&state_path).unwrap().contains("2 spots left"));
+    let _ = std::fs::remove_file(&state_path);
+}
+
+#[tokio::test]
+async fn reloads_program_list_without_restarting() {
+    let site = fake_site::start(vec![(Duration::ZERO, "2 spots left")]).await;
+    let state_path = std::env::temp_dir().join(format!("uwtennis-e2e-reload-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let config = |program: &str| -> Config {
+        toml::from_str(&format!(r#"
+            interval_seconds = 3600
+            site_url = "{url}"
+            state_path = "{state}"
+            program_ids = [{{ id = "{program}", name = "{program}" }}]
+        "#, url = site.url, state = state_path.display())).unwrap()
+    };
+    let state_mentions = |program: &str| {
+        std::fs::read_to_string(&state_path).is_ok_and(|state| state.contains(&format!("\"program_id\":\"{program}\"")))
+    };
+    let wait_for = async |program: &str| {
+        let started = std::time::Instant::now();
+        while !state_mentions(program) {
+            assert!(started.elapsed() < Duration::from_secs(5), "{program} was never checked");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    };
+
+    let (stop, stopping) = tokio::sync::watch::channel(false);
+    let (reload, reloads) = tokio::sync::mpsc::channel(1);
+    let monitor = tokio::spawn(run_until(Arc::new(config("before")), stopping, reloads));
+    wait_for("before").await;
+
+    // The new program is checked straight away and the removed one's sessions go
+    reload.send(config("after")).await.unwrap();
+    wait_for("after").await;
+    assert!(!state_mentions("before"));
+
+    stop.send(true).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), monitor).await.unwrap().unwrap().unwrap();
+    let _ = std::fs::remove_file(&state_path);
+}
+
+/// A local ntfy-style endpoint recording the body of everything posted to it, answering
+/// the first posts with `failures` (e.g. 503) and every one after them with 200
+async fn recorder(failures: Vec<u16>) -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+    let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let failures = Arc::new(std::sync::Mutex::new(failures.into_iter()));
+    let app = axum::Router::new().route("/", axum::routing::post({
+        let sent = sent.clone();
+        move |body: String| async move {
+            sent.lock().unwrap().push(body);
+            let status = failures.lock().unwrap().next().unwrap_or(200);
+            axum::http::StatusCode::from_u16(status).unwrap()
+        }
+    }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (endpoint, sent)
+}
@@ -0,0 +1,125 @@
+//! A tiny stand-in for the Warrior site: one program with a couple of sessions on the
+//! same date, one of whose spots badge follows a script over time
+
+use axum::Router;
+use axum::response::Html;
+use axum::routing::{get, post};
+use chrono::Days;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use uwtennis::clock::{Clock, SystemClock};
+use uwtennis::fetch::{FILTER_PATH, GET_PATH};
+
+// Sessions on the fake date as (ID, start, end, product); the first one's badge
+// follows the script and the others stay put
+pub const SESSIONS: [(&str, &str, &str, &str); 2] = [
+    ("evening", "19:00", "20:30", "Drop-in"),
+    ("early", "17:30", "19:00", "Early Drop-in"),
+];
+const STEADY_SPOTS: &str = "3 spots left";
+
+// Spots badge from each offset since the site started, in order
+pub type Script = Vec<(Duration, &'static str)>;
+
+struct Site {
+    started: Instant,
+    script: Script,
+    date: String,
+}
+
+impl Site {
+    fn spots(&self) -> &'static str {
+        let elapsed = self.started.elapsed();
+        self.script
+            .iter()
+            .rev()
+            .find(|(offset, _)| *offset <= elapsed)
+            .map_or("Full", |(_, spots)| spots)
+    }
+
+    fn calendar(&self) -> String {
+        let appointments = SESSIONS
+            .iter()
+            .map(|(id, start, end, product)| {
+                format!(
+                    r#"{{"ID":"{id}","StartDate":"{date}T{start}:00","EndDate":"{date}T{end}:00","Location":"PAC","ProductName":"{product}"}}"#,
+                    date = self.date,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let dates = format!(r#"["{}T00:00:00"]"#, self.date);
+        format!(
+            "<html><body><input id='ApptInfo' value='[{appointments}]'><input id='hdnDates' value='{dates}'></body></html>"
+        )
+    }
+
+    // Cards for the requested sessions
+    fn instances(&self, ids: &[String]) -> String {
+        SESSIONS
+            .iter()
+            .enumerate()
+            .filter(|(_, (id, ..))| ids.iter().any(|requested| requested == id))
+            .map(|(index, (id, ..))| {
+                let spots = if index == 0 { self.spots() } else { STEADY_SPOTS };
+                format!(
+                    "<div data-instance-appointmentid='{id}'><span class='spots-tag'>{spots}</span><span class='price'>$5.00</span></div>"
+                )
+            })
+            .collect()
+    }
+}
+
+// A running fake site
+pub struct FakeSite {
+    pub url: String,
+    // When the script's clock started
+    pub started: Instant,
+    // FilterProgramInstances requests served so far
+    pub filter_requests: Arc<AtomicUsize>,
+    // GetProgramInstances requests served so far
+    pub calendar_requests: Arc<AtomicUsize>,
+}
+
+/// Serve the fake site on a free local port
+pub async fn start(script: Script) -> FakeSite {
+    // Tomorrow evening at the facility, so the session is never closed out mid-test
+    let date = SystemClock.now().date_naive().checked_add_days(Days::new(1)).unwrap();
+    let site = Arc::new(Site { started: Instant::now(), script, date: date.format("%Y-%m-%d").to_string() });
+    let filter_requests = Arc::new(AtomicUsize::new(0));
+    let calendar_requests = Arc::new(AtomicUsize::new(0));
+
+    let app = Router::new()
+        .route(GET_PATH, get({
+            let site = site.clone();
+            let calendar_requests = calendar_requests.clone();
+            move || async move {
+                calendar_requests.fetch_add(1, Ordering::Relaxed);
+                Html(site.calendar())
+            }
+        }))
+        .route(FILTER_PATH, post({
+            let site = site.clone();
+            let filter_requests = filter_requests.clone();
+            move |form: String| async move {
+                filter_requests.fetch_add(1, Ordering::Relaxed);
+                Html(site.instances(&requested_ids(&form)))
+            }
+        }));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    FakeSite { url, started: site.started, filter_requests, calendar_requests }
+}
+
+/// Appointment IDs in a FilterProgramInstances form, from its `appointments[i][ID]` fields
+fn requested_ids(form: &str) -> Vec<String> {
+    form.split('&')
+        .filter_map(|field| field.split_once('='))
+        .filter(|(name, _)| name.ends_with("%5BID%5D"))
+        .map(|(_, id)| id.to_string())
+        .collect()
+}